@@ -1,8 +1,22 @@
-use crate::models::{Message, MessageChunk};
-use crate::state::AppState;
+use crate::models::{Message, MessageChunk, MessageError, MessagePersistError};
+use crate::services::agent::{AgentError, ChatBackend, ChatOptions};
+use crate::services::database::ChatRepository;
+use crate::services::title::generate_title;
+use crate::state::{AppState, GenerationHandle};
 use chrono::Utc;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
 use tauri::{Emitter, State, Window};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+// 写入数据库的最大尝试次数
+const PERSIST_MAX_ATTEMPTS: u32 = 3;
+// 每次重试前等待时间的基数
+const PERSIST_RETRY_DELAY_MS: u64 = 200;
+// 生成过程中每收到多少个响应块把已有内容写入数据库
+const PARTIAL_SAVE_INTERVAL_CHUNKS: usize = 10;
 
 #[tauri::command]
 pub async fn generate_ai_response(
@@ -11,53 +25,92 @@ pub async fn generate_ai_response(
     conversation_id: u64,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    info!("开始生成AI回复，对话ID: {}", conversation_id);
+    start_generation(window, &state, conversation_id)
+        .await
+        .map(|_| ())
+}
 
-    // 创建机器人消息占位符
-    let bot_message_id = Utc::now().timestamp_millis() as u64;
-    let bot_message = Message {
-        id: bot_message_id,
-        content: String::new(),
-        sender: "bot".to_string(),
-        timestamp: Utc::now().timestamp_millis() as u64,
-        conversation_id,
-    };
+/// 重新生成机器人回复，新回复和原回复作为同一条用户消息下的不同版本保留
+#[tauri::command]
+pub async fn regenerate_response(
+    window: Window,
+    message_id: u64,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let (conversation_id, previous_leaf) = state.repository.rewind_before_reply(message_id).await?;
+    info!(
+        "重新生成对话 {} 中消息 {} 的回复",
+        conversation_id, message_id
+    );
+    if let Err(e) = start_generation(window, &state, conversation_id).await {
+        // 没有生成新回复，回到原来的分支，避免原回复从界面上消失
+        if let Err(restore_error) = state
+            .repository
+            .set_active_leaf(conversation_id, previous_leaf)
+            .await
+        {
+            error!("恢复对话 {} 的分支失败: {}", conversation_id, restore_error);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
 
-    debug!("创建AI消息占位符: {:?}", bot_message);
+/// 根据对话当前分支上的消息生成回复，回复追加在分支末尾
+///
+/// 回复在后台生成，返回的通道在生成结束后收到最终的机器人消息
+pub(crate) async fn start_generation(
+    window: Window,
+    state: &AppState,
+    conversation_id: u64,
+) -> Result<oneshot::Receiver<Message>, String> {
+    info!("开始生成AI回复，对话ID: {}", conversation_id);
 
-    // 保存初始的空机器人消息
-    state.messages.lock().unwrap().push(bot_message);
+    // 先取出历史记录，再插入占位符，避免把空的回复发给模型
+    let history = state.repository.history(conversation_id).await?;
+    debug!("从历史记录中加载 {} 条消息", history.len());
 
-    // 获取Ollama代理
-    let agent = state.ollama_agent.clone();
+    // 获取当前的模型后端
+    let agent = state.agent();
 
-    let history = state.get_conversation_history(conversation_id);
-    let user_messages = history
-        .iter()
-        .filter(|msg| msg.sender == "user")
-        .map(|msg| msg.content.clone())
-        .collect::<Vec<String>>()
-        .join("\n\n");
-    debug!("从database中加载: {}", user_messages);
+    // 应用对话级别的模型设置
+    let options = load_chat_options(state, conversation_id).await;
+    debug!("对话 {} 的生成参数: {:?}", conversation_id, options);
 
     // 生成消息流
-    debug!("调用Ollama生成响应流");
-    let mut stream = match agent.generate_stream(&user_messages).await {
+    debug!("调用 {} 后端生成响应流", agent.provider());
+    let mut stream = match agent.chat_stream(&history, &options).await {
         Ok(stream) => {
-            info!("成功创建Ollama响应流");
+            info!("成功创建 {} 响应流", agent.provider());
             stream
         }
         Err(e) => {
-            error!("创建Ollama响应流失败: {}", e);
+            error!("创建 {} 响应流失败: {}", agent.provider(), e);
+            emit_message_error(&window, conversation_id, None, &e);
             return Err(format!("创建响应流失败: {}", e));
         }
     };
 
+    // 保存机器人消息占位符，生成结束前标记为不完整
+    let bot_message = state
+        .repository
+        .append_message(Message {
+            id: 0,
+            content: String::new(),
+            sender: "bot".to_string(),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            conversation_id,
+            partial: true,
+            parent_id: None,
+        })
+        .await?;
+    let bot_message_id = bot_message.id;
+    debug!("创建AI消息占位符: {:?}", bot_message);
+
     // 完整的响应内容
     let mut full_response = String::new();
 
-    let conv_arc = state.conversations.clone();
-    let msg_arc = state.messages.clone();
+    let repository = state.repository.clone();
     let config_arc = state.config.clone();
     let window_clone = window.clone();
 
@@ -65,6 +118,33 @@ pub async fn generate_ai_response(
     // 从配置中获取缓冲设置
     let buffer_size = config.app_behavior.message_chunk_buffer_size;
     let send_interval_ms = config.app_behavior.message_chunk_send_interval_ms;
+    // 自动标题设置，未指定标题模型时使用本次生成的模型
+    let title_settings = config.app_behavior.auto_title.then(|| TitleSettings {
+        model: Some(config.app_behavior.title_model.clone())
+            .filter(|model| !model.trim().is_empty())
+            .or_else(|| options.model.clone())
+            .unwrap_or_default(),
+        default_title: config.app_behavior.default_conversation_title.clone(),
+    });
+
+    // 登记本次生成，同一对话中仍在进行的旧生成会被取消
+    let (cancel_tx, mut cancel_rx) = oneshot::channel::<()>();
+    let generations = state.generations.clone();
+    {
+        let mut guard = generations.lock().unwrap();
+        if let Some(previous) = guard.insert(
+            conversation_id,
+            GenerationHandle {
+                message_id: bot_message_id,
+                cancel: cancel_tx,
+            },
+        ) {
+            info!("对话 {} 有未完成的生成，先取消", conversation_id);
+            let _ = previous.cancel.send(());
+        }
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel();
 
     // 启动另一个任务处理流
     debug!("启动异步任务处理响应流");
@@ -73,8 +153,29 @@ pub async fn generate_ai_response(
         let mut chunk_count = 0;
         let mut buffer = String::new();
         let mut last_emit_time = std::time::Instant::now();
+        let mut cancelled = false;
+        let mut stream_error: Option<AgentError> = None;
+        let (partial_tx, partial_writer) =
+            spawn_partial_writer(repository.clone(), bot_message.clone());
+
+        loop {
+            let chunk = tokio::select! {
+                _ = &mut cancel_rx => {
+                    info!("对话 {} 的生成已被取消", conversation_id);
+                    cancelled = true;
+                    break;
+                }
+                chunk = stream.next() => match chunk {
+                    Some(Ok(chunk)) => chunk,
+                    Some(Err(e)) => {
+                        error!("对话 {} 的响应流出错: {}", conversation_id, e);
+                        stream_error = Some(e);
+                        break;
+                    }
+                    None => break,
+                },
+            };
 
-        while let Some(chunk) = stream.next().await {
             // 将新的内容添加到完整响应中
             full_response.push_str(&chunk);
             buffer.push_str(&chunk);
@@ -92,6 +193,7 @@ pub async fn generate_ai_response(
                         conversation_id,
                         content: buffer.clone(),
                         is_complete: false,
+                        cancelled: false,
                     },
                 ) {
                     Ok(_) => {
@@ -102,39 +204,80 @@ pub async fn generate_ai_response(
                 }
             }
 
-            // 更频繁地更新消息内容，避免长时间锁等待
-            if chunk_count % 10 == 0 {
-                let mut msgs = msg_arc.lock().unwrap();
-                if let Some(msg) = msgs.iter_mut().find(|m| m.id == bot_message_id) {
-                    msg.content = full_response.clone();
-                }
+            // 定期写入已生成的内容，切换对话后重新加载也能看到进度
+            if chunk_count % PARTIAL_SAVE_INTERVAL_CHUNKS == 0 {
+                let _ = partial_tx.send(full_response.clone());
             }
         }
 
+        // 等待后台写入结束，避免较早的内容覆盖最终的回复
+        drop(partial_tx);
+        if let Err(e) = partial_writer.await {
+            error!("保存生成中消息的任务异常退出: {}", e);
+        }
+
         info!(
-            "流式响应完成，共 {} 个响应块，总长度 {} 字符",
+            "流式响应结束，共 {} 个响应块，总长度 {} 字符",
             chunk_count,
             full_response.len()
         );
 
-        // 更新对话
+        // 移除本次生成的登记（可能已被新的生成替换）
         {
-            let mut convs = conv_arc.lock().unwrap();
-            if let Some(conv) = convs.iter_mut().find(|c| c.id == conversation_id) {
-                conv.last_message = full_response.clone();
-                conv.timestamp = Utc::now().timestamp_millis() as u64;
+            let mut guard = generations.lock().unwrap();
+            if guard
+                .get(&conversation_id)
+                .is_some_and(|handle| handle.message_id == bot_message_id)
+            {
+                guard.remove(&conversation_id);
             }
         }
 
-        // 更新消息
-        {
-            let mut msgs = msg_arc.lock().unwrap();
-            if let Some(msg) = msgs.iter_mut().find(|m| m.id == bot_message_id) {
-                msg.content = full_response;
+        // 发送缓冲区中剩余的内容
+        if !buffer.is_empty() {
+            if let Err(e) = window.emit(
+                "message_chunk",
+                MessageChunk {
+                    conversation_id,
+                    content: buffer,
+                    is_complete: false,
+                    cancelled: false,
+                },
+            ) {
+                error!("发送消息块到前端失败: {}", e);
             }
         }
 
-        // 发送完成信号
+        // 更新消息，被取消或出错中断的回复标记为不完整
+        let final_message = Message {
+            content: full_response,
+            partial: cancelled || stream_error.is_some(),
+            ..bot_message
+        };
+
+        // 写入数据库，对话在生成期间被删除时不再保存
+        match repository.conversation(conversation_id).await {
+            Ok(Some(_)) => persist_bot_reply(&window, &repository, &final_message).await,
+            Ok(None) => info!("对话 {} 已被删除，丢弃生成的回复", conversation_id),
+            Err(e) => error!("读取对话 {} 失败: {}", conversation_id, e),
+        }
+
+        // 完整的回复才用来生成标题，不阻塞完成信号
+        if let Some(settings) = title_settings.filter(|_| !final_message.partial) {
+            tokio::spawn(auto_title(
+                window.clone(),
+                repository.clone(),
+                agent,
+                conversation_id,
+                settings,
+            ));
+        }
+
+        if let Some(e) = &stream_error {
+            emit_message_error(&window, conversation_id, Some(bot_message_id), e);
+        }
+
+        // 发送完成信号，被取消时带上 cancelled 标记
         window_clone
             .emit(
                 "message_chunk",
@@ -142,10 +285,211 @@ pub async fn generate_ai_response(
                     conversation_id,
                     content: String::new(),
                     is_complete: true,
+                    cancelled,
                 },
             )
             .unwrap();
+
+        // 调用方可能不关心最终结果
+        let _ = reply_tx.send(final_message);
     });
 
-    Ok(())
+    Ok(reply_rx)
+}
+
+struct TitleSettings {
+    model: String,
+    /// 仍是默认标题的对话才会自动命名
+    default_title: String,
+}
+
+/// 对话刚完成第一轮问答且还是默认标题时生成标题，并通过 conversation_updated 事件通知前端
+async fn auto_title(
+    window: Window,
+    repository: ChatRepository,
+    agent: Arc<dyn ChatBackend>,
+    conversation_id: u64,
+    settings: TitleSettings,
+) {
+    match repository.conversation(conversation_id).await {
+        Ok(Some(conversation)) if conversation.title == settings.default_title => {}
+        _ => return,
+    }
+    let history = match repository.history(conversation_id).await {
+        Ok(history) => history,
+        Err(e) => {
+            warn!("读取对话 {} 失败，跳过生成标题: {}", conversation_id, e);
+            return;
+        }
+    };
+    let mut questions = history.iter().filter(|m| m.sender == "user");
+    let (Some(question), None) = (questions.next(), questions.next()) else {
+        return;
+    };
+    let Some(answer) = history.last().filter(|m| m.sender == "bot") else {
+        return;
+    };
+
+    let title = match generate_title(
+        agent.as_ref(),
+        &settings.model,
+        &question.content,
+        &answer.content,
+    )
+    .await
+    {
+        Ok(Some(title)) => title,
+        Ok(None) => {
+            warn!("模型没有为对话 {} 生成可用的标题", conversation_id);
+            return;
+        }
+        Err(e) => {
+            warn!("为对话 {} 生成标题失败: {}", conversation_id, e);
+            return;
+        }
+    };
+
+    // 生成期间用户可能已经手动改了标题
+    match repository
+        .replace_title(conversation_id, settings.default_title, title)
+        .await
+    {
+        Ok(Some(conversation)) => {
+            info!(
+                "对话 {} 自动命名为: {}",
+                conversation_id, conversation.title
+            );
+            if let Err(e) = window.emit("conversation_updated", conversation) {
+                error!("发送对话更新事件到前端失败: {}", e);
+            }
+        }
+        Ok(None) => debug!("对话 {} 的标题已被修改，不再自动命名", conversation_id),
+        Err(e) => error!("保存对话 {} 的标题失败: {}", conversation_id, e),
+    }
+}
+
+/// 读取对话的模型设置，读取失败时使用全局配置
+async fn load_chat_options(state: &AppState, conversation_id: u64) -> ChatOptions {
+    match state
+        .repository
+        .conversation_settings(conversation_id)
+        .await
+    {
+        Ok(Some(settings)) => settings.into(),
+        Ok(None) => ChatOptions::default(),
+        Err(e) => {
+            warn!("读取对话 {} 的模型设置失败: {}", conversation_id, e);
+            ChatOptions::default()
+        }
+    }
+}
+
+/// 通知前端生成失败的原因，前端可据此提示用户或提供重试
+fn emit_message_error(
+    window: &Window,
+    conversation_id: u64,
+    message_id: Option<u64>,
+    e: &AgentError,
+) {
+    if let Err(emit_err) = window.emit(
+        "message_error",
+        MessageError {
+            conversation_id,
+            message_id,
+            kind: e.kind().to_string(),
+            message: e.to_string(),
+            retryable: e.is_retryable(),
+        },
+    ) {
+        error!("发送错误事件到前端失败: {}", emit_err);
+    }
+}
+
+/// 将机器人回复写入数据库并更新所属对话，失败时重试并通知前端
+async fn persist_bot_reply(window: &Window, repository: &ChatRepository, message: &Message) {
+    let mut last_error = String::new();
+
+    for attempt in 1..=PERSIST_MAX_ATTEMPTS {
+        match repository.save_message_with_preview(message.clone()).await {
+            Ok(_) => {
+                info!(
+                    "机器人消息 {} 已保存到数据库 (partial: {})",
+                    message.id, message.partial
+                );
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    "保存机器人消息 {} 失败 (第 {}/{} 次): {}",
+                    message.id, attempt, PERSIST_MAX_ATTEMPTS, e
+                );
+                last_error = e;
+                if attempt < PERSIST_MAX_ATTEMPTS {
+                    tokio::time::sleep(Duration::from_millis(
+                        PERSIST_RETRY_DELAY_MS * attempt as u64,
+                    ))
+                    .await;
+                }
+            }
+        }
+    }
+
+    error!("保存机器人消息 {} 最终失败: {}", message.id, last_error);
+    if let Err(e) = window.emit(
+        "message_persist_error",
+        MessagePersistError {
+            conversation_id: message.conversation_id,
+            message_id: message.id,
+            attempts: PERSIST_MAX_ATTEMPTS,
+            error: last_error,
+        },
+    ) {
+        error!("发送保存失败事件到前端失败: {}", e);
+    }
+}
+
+// 在后台写入生成中的回复，不阻塞读取响应流；写入跟不上时跳过中间的内容，只保存最新的
+fn spawn_partial_writer(
+    repository: ChatRepository,
+    message: Message,
+) -> (watch::Sender<String>, JoinHandle<()>) {
+    let (sender, mut receiver) = watch::channel(String::new());
+    let writer = tokio::spawn(async move {
+        while receiver.changed().await.is_ok() {
+            let partial = Message {
+                content: receiver.borrow_and_update().clone(),
+                ..message.clone()
+            };
+            if let Err(e) = repository.save_message(partial).await {
+                warn!("保存生成中的消息 {} 失败: {}", message.id, e);
+            }
+        }
+    });
+    (sender, writer)
+}
+
+#[tauri::command]
+pub fn stop_generation(conversation_id: u64, state: State<AppState>) -> Result<bool, String> {
+    let stopped = cancel_generation(&state, conversation_id);
+    if !stopped {
+        debug!("对话 {} 没有正在进行的生成", conversation_id);
+    }
+    Ok(stopped)
+}
+
+// 取消对话中正在进行的生成，返回是否有需要取消的生成
+pub(crate) fn cancel_generation(state: &AppState, conversation_id: u64) -> bool {
+    let handle = state.generations.lock().unwrap().remove(&conversation_id);
+    match handle {
+        Some(handle) => {
+            info!(
+                "停止对话 {} 的生成，消息ID: {}",
+                conversation_id, handle.message_id
+            );
+            // 任务可能恰好已经结束，发送失败可以忽略
+            let _ = handle.cancel.send(());
+            true
+        }
+        None => false,
+    }
 }
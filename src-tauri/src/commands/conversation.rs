@@ -1,75 +1,215 @@
-use crate::models::{Conversation, Message};
+use crate::commands::ai::cancel_generation;
+use crate::models::{Conversation, ConversationFilter, ConversationSettings, Message};
 use crate::state::AppState;
 use chrono::Utc;
 use log::{error, info};
 use tauri::State;
 
+// 标题和标签的最大字符数
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_TAG_CHARS: usize = 32;
+
+/// 获取对话列表，不传筛选条件时返回所有未归档的对话，按最后活动时间排序，置顶的在前
 #[tauri::command]
-pub fn get_conversations(state: State<AppState>) -> Vec<Conversation> {
-    state.conversations.lock().unwrap().clone()
+pub async fn get_conversations(
+    filter: Option<ConversationFilter>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Conversation>, String> {
+    let mut filter = filter.unwrap_or_default();
+    filter.tags = normalize_tags(filter.tags)?;
+    state.repository.list_conversations(filter).await
 }
 
 #[tauri::command]
-pub fn get_conversation_messages(conversation_id: u64, state: State<AppState>) -> Vec<Message> {
-    let messages = state.get_conversation_history(conversation_id);
-    messages
-        .iter()
-        .filter(|m| m.conversation_id == conversation_id)
-        .cloned()
-        .collect()
+pub async fn rename_conversation(
+    conversation_id: u64,
+    title: String,
+    state: State<'_, AppState>,
+) -> Result<Conversation, String> {
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        return Err("标题不能为空".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!("标题不能超过 {} 个字符", MAX_TITLE_CHARS));
+    }
+
+    let conversation = state
+        .repository
+        .rename_conversation(conversation_id, title)
+        .await?;
+    info!("对话 {} 重命名为: {}", conversation_id, conversation.title);
+    Ok(conversation)
 }
 
 #[tauri::command]
-pub fn create_conversation(title: String, state: State<AppState>) -> Result<Conversation, String> {
-    let mut conversations = state.conversations.lock().unwrap();
-
-    // 生成新ID
-    let new_id = conversations.iter().map(|c| c.id).max().unwrap_or(0) + 1;
-
-    // 创建新对话
-    let new_conversation = Conversation {
-        id: new_id,
-        title,
-        last_message: "开始新的对话".to_string(),
-        timestamp: Utc::now().timestamp_millis() as u64,
-    };
+pub async fn set_conversation_pinned(
+    conversation_id: u64,
+    pinned: bool,
+    state: State<'_, AppState>,
+) -> Result<Conversation, String> {
+    let conversation = state.repository.set_pinned(conversation_id, pinned).await?;
+    info!("对话 {} 置顶: {}", conversation_id, pinned);
+    Ok(conversation)
+}
 
-    // 创建对话后尝试保存到数据库
-    if let Ok(mut db_guard) = state.db.lock() {
-        if let Some(ref mut db) = *db_guard {
-            if let Err(e) = db.save_conversation(&new_conversation) {
-                error!("保存新对话到数据库失败: {}", e);
-            }
+#[tauri::command]
+pub async fn set_conversation_archived(
+    conversation_id: u64,
+    archived: bool,
+    state: State<'_, AppState>,
+) -> Result<Conversation, String> {
+    let conversation = state
+        .repository
+        .set_archived(conversation_id, archived)
+        .await?;
+    info!("对话 {} 归档: {}", conversation_id, archived);
+    Ok(conversation)
+}
+
+/// 设置对话的标签，替换原有的全部标签
+#[tauri::command]
+pub async fn set_conversation_tags(
+    conversation_id: u64,
+    tags: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<Conversation, String> {
+    let tags = normalize_tags(tags)?;
+    let conversation = state.repository.set_tags(conversation_id, tags).await?;
+    info!("对话 {} 的标签: {:?}", conversation_id, conversation.tags);
+    Ok(conversation)
+}
+
+/// 所有对话用过的标签，用于筛选
+#[tauri::command]
+pub async fn get_conversation_tags(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    state.repository.tags().await
+}
+
+// 去掉首尾空白、空标签和重复的标签
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() || normalized.iter().any(|t| t == tag) {
+            continue;
         }
+        if tag.chars().count() > MAX_TAG_CHARS {
+            return Err(format!("标签不能超过 {} 个字符: {}", MAX_TAG_CHARS, tag));
+        }
+        normalized.push(tag.to_string());
     }
+    Ok(normalized)
+}
 
-    // 添加到对话列表
-    conversations.push(new_conversation.clone());
+/// 分页获取对话消息，按时间顺序返回
+///
+/// `before` 为空时返回最新的消息，否则返回ID小于 `before` 的消息，
+/// 前端向上滚动时传入当前最早一条消息的ID加载更早的记录。`limit` 为空时返回全部。
+#[tauri::command]
+pub async fn get_conversation_messages(
+    conversation_id: u64,
+    before: Option<u64>,
+    limit: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Message>, String> {
+    state
+        .repository
+        .messages(conversation_id, before, limit)
+        .await
+}
+
+#[tauri::command]
+pub async fn create_conversation(
+    title: String,
+    state: State<'_, AppState>,
+) -> Result<Conversation, String> {
+    // 保存到数据库并由数据库分配ID
+    let new_conversation = state
+        .repository
+        .create_conversation(Conversation {
+            id: 0,
+            title,
+            last_message: "开始新的对话".to_string(),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+        })
+        .await
+        .map_err(|e| {
+            error!("保存新对话到数据库失败: {}", e);
+            e
+        })?;
 
     info!("创建了新对话: {:?}", new_conversation);
     Ok(new_conversation)
 }
 
 #[tauri::command]
-pub fn delete_conversation(conversation_id: u64, state: State<AppState>) -> Result<(), String> {
-    // 删除对话
-    {
-        let mut conversations = state.conversations.lock().unwrap();
-        let position = conversations
-            .iter()
-            .position(|c| c.id == conversation_id)
-            .ok_or_else(|| format!("对话 {} 不存在", conversation_id))?;
-
-        conversations.remove(position);
-        info!("删除了对话 {}", conversation_id);
-    }
+pub async fn delete_conversation(
+    conversation_id: u64,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    // 先停止进行中的生成，避免继续写入已删除的对话
+    cancel_generation(&state, conversation_id);
+
+    // 消息和设置随对话一起删除
+    state
+        .repository
+        .delete_conversation(conversation_id)
+        .await?;
+    info!("删除了对话 {} 及其消息", conversation_id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_conversation_settings(
+    conversation_id: u64,
+    state: State<'_, AppState>,
+) -> Result<ConversationSettings, String> {
+    let settings = state
+        .repository
+        .conversation_settings(conversation_id)
+        .await?;
 
-    // 删除关联的消息
+    Ok(settings.unwrap_or(ConversationSettings {
+        conversation_id,
+        ..Default::default()
+    }))
+}
+
+#[tauri::command]
+pub async fn update_conversation_settings(
+    settings: ConversationSettings,
+    state: State<'_, AppState>,
+) -> Result<ConversationSettings, String> {
+    // 空字符串视为未设置
+    let non_empty = |value: Option<String>| value.filter(|v| !v.trim().is_empty());
+    let settings = ConversationSettings {
+        model_name: non_empty(settings.model_name),
+        system_prompt: non_empty(settings.system_prompt),
+        ..settings
+    };
+
+    if settings
+        .temperature
+        .is_some_and(|t| !(0.0..=2.0).contains(&t))
     {
-        let mut messages = state.messages.lock().unwrap();
-        messages.retain(|m| m.conversation_id != conversation_id);
-        info!("删除了对话 {} 相关的所有消息", conversation_id);
+        return Err("temperature 必须在 0 到 2 之间".to_string());
+    }
+    if settings.top_p.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+        return Err("top_p 必须在 0 到 1 之间".to_string());
+    }
+    if settings.num_ctx == Some(0) {
+        return Err("num_ctx 必须大于 0".to_string());
     }
 
-    Ok(())
+    state
+        .repository
+        .save_conversation_settings(settings.clone())
+        .await?;
+
+    info!("更新了对话 {} 的模型设置", settings.conversation_id);
+    Ok(settings)
 }
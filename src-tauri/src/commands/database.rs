@@ -1,14 +1,21 @@
-use crate::{models::Conversation, state::AppState};
+use crate::{
+    commands::ai::cancel_generation,
+    models::{Conversation, SearchHit},
+    state::AppState,
+};
 use tauri::State;
 
+/// 启动时打开数据库失败的原因，此时不会保存任何对话，前端需要提示用户
+#[tauri::command]
+pub fn get_database_error(state: State<AppState>) -> Result<Option<String>, String> {
+    Ok(state.database_error.lock().unwrap().clone())
+}
+
 #[tauri::command]
 pub async fn get_database_conversations(
     state: State<'_, AppState>,
 ) -> Result<Vec<Conversation>, String> {
-    let db_arc = state.db.clone();
-    let guard = db_arc.lock().unwrap();
-    let db = guard.as_ref().unwrap();
-    db.get_all_conversations().map_err(|e| e.to_string())
+    state.repository.conversations().await
 }
 
 #[tauri::command]
@@ -16,9 +23,21 @@ pub async fn delete_database_conversation(
     state: State<'_, AppState>,
     conversation_id: u64,
 ) -> Result<(), String> {
-    let db_arc = state.db.clone();
-    let mut guard = db_arc.lock().unwrap();
-    let db = guard.as_mut().unwrap();
-    db.delete_conversation(conversation_id)
-        .map_err(|e| e.to_string())
+    // 先停止进行中的生成，避免继续写入已删除的对话
+    cancel_generation(&state, conversation_id);
+    state.repository.delete_conversation(conversation_id).await
+}
+
+/// 在所有对话中搜索消息
+#[tauri::command]
+pub async fn search_messages(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Vec<SearchHit>, String> {
+    state
+        .repository
+        .search_messages(query, limit.unwrap_or(20).min(100), offset.unwrap_or(0))
+        .await
 }
@@ -0,0 +1,62 @@
+use crate::services::export::{collect_conversation, render, ExportFormat};
+use crate::state::AppState;
+use log::info;
+use std::fs;
+use std::path::Path;
+use tauri::State;
+
+fn write_export(path: &str, content: &str) -> Result<(), String> {
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|e| format!("创建导出目录失败: {}", e))?;
+        }
+    }
+    fs::write(path, content).map_err(|e| format!("写入导出文件失败: {}", e))
+}
+
+/// 导出单个对话到指定文件
+#[tauri::command]
+pub async fn export_conversation(
+    state: State<'_, AppState>,
+    conversation_id: u64,
+    format: ExportFormat,
+    path: String,
+) -> Result<(), String> {
+    let exported = state
+        .repository
+        .read(move |db| collect_conversation(db, conversation_id))
+        .await?
+        .ok_or_else(|| format!("对话 {} 不存在", conversation_id))?;
+
+    let content = render(format, &[exported])?;
+    write_export(&path, &content)?;
+    info!("对话 {} 已导出到 {}", conversation_id, path);
+    Ok(())
+}
+
+/// 导出所有对话到同一个文件，返回导出的对话数量
+#[tauri::command]
+pub async fn export_all_conversations(
+    state: State<'_, AppState>,
+    format: ExportFormat,
+    path: String,
+) -> Result<usize, String> {
+    let conversations = state
+        .repository
+        .read(|db| {
+            let mut conversations = Vec::new();
+            // 按最后更新时间先后排列，最近更新的对话在最后
+            for conversation in db.get_all_conversations()?.iter().rev() {
+                if let Some(exported) = collect_conversation(db, conversation.id)? {
+                    conversations.push(exported);
+                }
+            }
+            Ok(conversations)
+        })
+        .await?;
+
+    let content = render(format, &conversations)?;
+    write_export(&path, &content)?;
+    info!("{} 个对话已导出到 {}", conversations.len(), path);
+    Ok(conversations.len())
+}
@@ -0,0 +1,68 @@
+use crate::models::{ImportReport, SkippedItem};
+use crate::services::import::parse_import;
+use crate::state::AppState;
+use log::{info, warn};
+use std::fs;
+use tauri::State;
+
+/// 导入对话，支持本程序导出的 JSON、ChatGPT 的 conversations.json 和 Open WebUI 导出文件
+#[tauri::command]
+pub async fn import_conversations(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<ImportReport, String> {
+    // 读取和解析大文件比较耗时，不占用异步运行时的线程
+    let parsed = tokio::task::spawn_blocking(move || {
+        let content = fs::read_to_string(&path).map_err(|e| format!("读取导入文件失败: {}", e))?;
+        parse_import(&content)
+    })
+    .await
+    .map_err(|e| format!("解析导入文件的任务异常退出: {}", e))??;
+
+    let mut skipped = parsed.skipped;
+    let mut conversations = 0;
+    let mut messages = 0;
+    for exported in parsed.conversations {
+        let title = exported.conversation.title.clone();
+        let count = exported.messages.len();
+        // 每个对话单独提交，失败的对话记入跳过列表，不影响其他对话
+        let result = state
+            .repository
+            .write(move |db| {
+                db.import_conversation(
+                    &exported.conversation,
+                    &exported.messages,
+                    exported.settings.as_ref(),
+                )
+            })
+            .await;
+        match result {
+            Ok(_) => {
+                conversations += 1;
+                messages += count;
+            }
+            Err(e) => {
+                warn!("导入对话 {} 失败: {}", title, e);
+                skipped.push(SkippedItem {
+                    conversation: title,
+                    message: None,
+                    reason: format!("保存失败: {}", e),
+                });
+            }
+        }
+    }
+
+    info!(
+        "从 {} 导入了 {} 个对话、{} 条消息，跳过 {} 项",
+        parsed.source,
+        conversations,
+        messages,
+        skipped.len()
+    );
+    Ok(ImportReport {
+        source: parsed.source.to_string(),
+        conversations,
+        messages,
+        skipped,
+    })
+}
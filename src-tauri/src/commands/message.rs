@@ -1,64 +1,92 @@
-use crate::models::Message;
+use crate::models::{Message, MessageBranch};
 use crate::state::AppState;
 use chrono::Utc;
 use log::{debug, error, info};
 use tauri::State;
 
 #[tauri::command]
-pub fn send_user_message(
+pub async fn send_user_message(
     content: String,
     conversation_id: u64,
-    state: State<AppState>,
+    state: State<'_, AppState>,
+) -> Result<Message, String> {
+    append_user_message(&state, conversation_id, content).await
+}
+
+/// 把用户消息追加到对话当前分支的末尾，并更新对话的最后消息
+pub(crate) async fn append_user_message(
+    state: &AppState,
+    conversation_id: u64,
+    content: String,
 ) -> Result<Message, String> {
     info!("接收用户消息，对话ID: {}", conversation_id);
     debug!("消息内容: {}", content);
 
-    // 创建用户消息
-    let user_message = Message {
-        id: Utc::now().timestamp_millis() as u64,
-        content: content.clone(),
-        sender: "user".to_string(),
-        timestamp: Utc::now().timestamp_millis() as u64,
-        conversation_id,
-    };
+    // 创建用户消息，ID由数据库分配
+    let user_message = state
+        .repository
+        .append_message(Message {
+            id: 0,
+            content: content.clone(),
+            sender: "user".to_string(),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            conversation_id,
+            partial: false,
+            parent_id: None,
+        })
+        .await?;
 
     debug!("创建的用户消息: {:?}", user_message);
 
-    // 存储用户消息
-    state.messages.lock().unwrap().push(user_message.clone());
-
-    // 尝试保存到数据库
-    if let Ok(mut db_guard) = state.db.lock() {
-        if let Some(ref mut db) = *db_guard {
-            if let Err(e) = db.save_message(&user_message) {
-                error!("保存用户消息到数据库失败: {}", e);
-            }
-        }
-    }
-
-    // 更新对话的最后消息时间
-    if let Some(conv) = state
-        .conversations
-        .lock()
-        .unwrap()
-        .iter_mut()
-        .find(|c| c.id == conversation_id)
+    // 更新对话的最后消息和时间
+    if let Err(e) = state
+        .repository
+        .update_conversation_preview(conversation_id, content, user_message.timestamp)
+        .await
     {
-        conv.last_message = content;
-        debug!("更新对话 {} 的时间戳", conversation_id);
-        conv.timestamp = user_message.timestamp;
-        // 更新数据库中的对话
-        if let Ok(mut db_guard) = state.db.lock() {
-            if let Some(ref mut db) = *db_guard {
-                if let Err(e) = db.save_conversation(conv) {
-                    error!("更新对话到数据库失败: {}", e);
-                }
-            }
-        }
-    } else {
-        info!("未找到对话ID: {}", conversation_id);
+        error!("更新对话 {} 失败: {}", conversation_id, e);
     }
 
     info!("用户消息处理完成");
     Ok(user_message)
 }
+
+/// 编辑用户消息，编辑后的内容作为新的分支，原来的消息和回复仍然保留
+///
+/// 返回新消息，之后调用 generate_ai_response 为它生成回复
+#[tauri::command]
+pub async fn edit_user_message(
+    message_id: u64,
+    content: String,
+    state: State<'_, AppState>,
+) -> Result<Message, String> {
+    let message = state
+        .repository
+        .edit_user_message(message_id, content)
+        .await?;
+    info!("编辑消息 {}，新分支消息ID: {}", message_id, message.id);
+    Ok(message)
+}
+
+/// 切换到包含指定消息的分支，返回切换后当前分支上的全部消息
+#[tauri::command]
+pub async fn switch_branch(
+    message_id: u64,
+    state: State<'_, AppState>,
+) -> Result<Vec<Message>, String> {
+    let conversation_id = state.repository.switch_branch(message_id).await?;
+    info!(
+        "对话 {} 切换到消息 {} 所在的分支",
+        conversation_id, message_id
+    );
+    state.repository.history(conversation_id).await
+}
+
+/// 当前分支上有多个版本的消息，前端据此显示版本切换
+#[tauri::command]
+pub async fn get_message_branches(
+    conversation_id: u64,
+    state: State<'_, AppState>,
+) -> Result<Vec<MessageBranch>, String> {
+    state.repository.branches(conversation_id).await
+}
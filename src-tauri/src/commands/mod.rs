@@ -1,11 +1,19 @@
 pub mod ai;
 pub mod conversation;
 pub mod database;
+pub mod export;
+pub mod import;
 pub mod message;
+pub mod model;
+pub mod talk;
 pub mod voice;
 
 pub use ai::*;
 pub use conversation::*;
 pub use database::*;
+pub use export::*;
+pub use import::*;
 pub use message::*;
+pub use model::*;
+pub use talk::*;
 pub use voice::*;
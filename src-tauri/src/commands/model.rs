@@ -0,0 +1,119 @@
+use crate::services::agent::{ModelDetails, ModelSummary};
+use crate::state::AppState;
+use log::{debug, error, info, warn};
+use tauri::{Emitter, State, Window};
+use tokio_stream::StreamExt;
+
+#[tauri::command]
+pub async fn list_models(state: State<'_, AppState>) -> Result<Vec<ModelSummary>, String> {
+    let agent = state.agent();
+    agent.list_models().await.map_err(|e| {
+        error!("获取模型列表失败: {}", e);
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+pub async fn pull_model(
+    window: Window,
+    model_name: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    info!("开始下载模型: {}", model_name);
+    let agent = state.agent();
+
+    let mut stream = agent.pull_model(&model_name).await.map_err(|e| {
+        error!("下载模型 {} 失败: {}", model_name, e);
+        e.to_string()
+    })?;
+
+    // 逐条转发下载进度到前端
+    while let Some(progress) = stream.next().await {
+        match progress {
+            Ok(progress) => {
+                debug!("模型下载进度: {:?}", progress);
+                if let Err(e) = window.emit("model_pull_progress", progress) {
+                    error!("发送下载进度到前端失败: {}", e);
+                }
+            }
+            Err(e) => {
+                error!("下载模型 {} 时出错: {}", model_name, e);
+                return Err(e.to_string());
+            }
+        }
+    }
+
+    info!("模型 {} 下载完成", model_name);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_model(model_name: String, state: State<'_, AppState>) -> Result<(), String> {
+    let agent = state.agent();
+    if agent.model() == model_name {
+        warn!("删除的是当前正在使用的模型: {}", model_name);
+    }
+
+    agent.delete_model(&model_name).await.map_err(|e| {
+        error!("删除模型 {} 失败: {}", model_name, e);
+        e.to_string()
+    })?;
+    info!("已删除模型: {}", model_name);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn show_model_info(
+    model_name: String,
+    state: State<'_, AppState>,
+) -> Result<ModelDetails, String> {
+    let agent = state.agent();
+    agent.show_model(&model_name).await.map_err(|e| {
+        error!("获取模型 {} 信息失败: {}", model_name, e);
+        e.to_string()
+    })
+}
+
+/// 运行时切换当前使用的模型，并写入配置文件
+#[tauri::command]
+pub async fn set_active_model(
+    window: Window,
+    model_name: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let agent = state.agent();
+
+    // 服务端可访问时确认模型存在，Ollama 的模型名省略标签时默认为 latest
+    match agent.list_models().await {
+        Ok(models) => {
+            let exists = models
+                .iter()
+                .any(|m| m.name == model_name || m.name == format!("{}:latest", model_name));
+            if !exists {
+                return Err(format!("模型 {} 不存在，请先下载该模型", model_name));
+            }
+        }
+        Err(e) => warn!("无法获取模型列表，跳过模型检查: {}", e),
+    }
+
+    // 先写入配置文件，保存失败时不切换模型
+    let config = {
+        let binding = state.config.clone();
+        let mut config = binding.lock().expect("获取配置失败");
+        let mut updated = config.clone();
+        updated.ai_model.model_name = model_name.clone();
+        match updated.clone().get_config_file_path() {
+            Some(path) => updated.save_config(&updated, &path)?,
+            None => return Err("无法确定配置文件路径".to_string()),
+        }
+        *config = updated.clone();
+        updated
+    };
+    agent.set_model(&model_name);
+    if let Err(e) = window.emit("config_changed", &config) {
+        error!("发送配置变更事件失败: {}", e);
+    }
+
+    info!("当前模型已切换为: {}", model_name);
+    Ok(())
+}
@@ -0,0 +1,392 @@
+use crate::commands::ai::start_generation;
+use crate::commands::message::append_user_message;
+use crate::services::asr::{VoskASR, VoskStream};
+use crate::services::tts::speaker::{speech_sentences, Playback, Speaker};
+use crate::state::{AppState, TalkSession, VoiceControl};
+use crate::utils::config::VadConfig;
+use log::{debug, error, info, warn};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager, State, Window};
+use tokio::sync::{oneshot, OwnedMutexGuard};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+
+static NEXT_TALK_ID: AtomicU64 = AtomicU64::new(1);
+
+// 朗读时麦克风也会录到回复的声音，检测打断时提高语音检测的门槛，
+// 只有比回声明显更响、持续更久的说话才算打断
+const BARGE_IN_EXTRA_THRESHOLD_DB: f32 = 12.0;
+const BARGE_IN_MIN_SPEECH_MS: u64 = 400;
+
+/// 朗读回复的结束方式
+enum SpeakEnd {
+    Finished,
+    /// 用户开口打断了朗读
+    Interrupted,
+    Stopped,
+}
+
+/// 开始对话模式：听完用户的一句话后发给模型，朗读回复，然后继续听
+///
+/// 状态变化通过 `talk_state` 事件通知前端（listening、thinking、speaking、stopped、error），
+/// 识别中的文本通过 `talk_partial` 发送，新的用户消息和机器人回复通过 `talk_message` 发送。
+/// 进行中的语音输入和对话模式会先被停止，麦克风打开后返回
+#[tauri::command]
+pub async fn start_talk_mode(
+    window: Window,
+    conversation_id: u64,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    if !talk_mode_available()? {
+        return Err("构建时没有启用 python-tts 特性，无法使用对话模式".to_string());
+    }
+    info!("开始对话模式，对话ID: {}", conversation_id);
+
+    // 麦克风同一时间只给一个任务使用
+    if let Some(session) = state.voice_session.lock().unwrap().take() {
+        info!("停止对话 {} 的语音输入", session.conversation_id);
+        let _ = session.control.send(VoiceControl::Cancel);
+    }
+    stop_talk_session(&state);
+
+    let id = NEXT_TALK_ID.fetch_add(1, Ordering::SeqCst);
+    let (stop_tx, stop_rx) = oneshot::channel();
+    state.talk_session.lock().unwrap().replace(TalkSession {
+        id,
+        conversation_id,
+        stop: stop_tx,
+    });
+
+    let (ready_tx, ready_rx) = oneshot::channel();
+    tokio::spawn(run_talk(window, id, conversation_id, stop_rx, ready_tx));
+
+    // 等待语音合成加载完成、麦克风打开，失败时把错误返回给前端
+    ready_rx
+        .await
+        .map_err(|_| "对话模式任务意外结束".to_string())?
+}
+
+/// 当前构建是否支持对话模式，朗读回复需要 python-tts 特性
+#[tauri::command]
+pub fn talk_mode_available() -> Result<bool, String> {
+    Ok(cfg!(feature = "python-tts"))
+}
+
+/// 停止对话模式，正在生成的回复会继续生成并保存
+#[tauri::command]
+pub fn stop_talk_mode(state: State<AppState>) -> Result<bool, String> {
+    let stopped = stop_talk_session(&state);
+    if !stopped {
+        debug!("没有正在进行的对话模式");
+    }
+    Ok(stopped)
+}
+
+// 通知正在进行的对话模式退出
+pub(crate) fn stop_talk_session(state: &AppState) -> bool {
+    match state.talk_session.lock().unwrap().take() {
+        Some(session) => {
+            info!("停止对话 {} 的对话模式", session.conversation_id);
+            // 任务可能恰好已经结束，发送失败可以忽略
+            let _ = session.stop.send(());
+            true
+        }
+        None => false,
+    }
+}
+
+async fn run_talk(
+    window: Window,
+    id: u64,
+    conversation_id: u64,
+    mut stop: oneshot::Receiver<()>,
+    ready: oneshot::Sender<Result<(), String>>,
+) {
+    let app = window.app_handle().clone();
+    let state = app.state::<AppState>();
+
+    let result = talk_loop(&window, &state, conversation_id, &mut stop, ready).await;
+    remove_session(&state.talk_session, id);
+
+    match result {
+        Ok(()) => {
+            info!("对话模式已结束");
+            let _ = window.emit("talk_state", "stopped");
+        }
+        Err(e) => {
+            error!("对话模式出错: {}", e);
+            let _ = window.emit("talk_state", "error");
+        }
+    }
+}
+
+async fn talk_loop(
+    window: &Window,
+    state: &AppState,
+    conversation_id: u64,
+    stop: &mut oneshot::Receiver<()>,
+    ready: oneshot::Sender<Result<(), String>>,
+) -> Result<(), String> {
+    let (barge_in, vad) = {
+        let config = state.config.lock().unwrap();
+        (config.voice.barge_in, config.voice.vad.clone())
+    };
+    let started = async {
+        let speaker = Speaker::new().await?;
+        let listener = Listener::open(window, state.vosk_asr.clone(), &vad, false).await?;
+        Ok::<_, String>((speaker, listener))
+    };
+    let (speaker, mut listener) = match started.await {
+        Ok(started) => {
+            let _ = ready.send(Ok(()));
+            started
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e.clone()));
+            return Err(e);
+        }
+    };
+
+    loop {
+        let _ = window.emit("talk_state", "listening");
+        let heard = listener.listen(window, stop).await;
+        listener.close();
+        let Some(text) = heard? else {
+            return Ok(());
+        };
+        if text.trim().is_empty() {
+            listener = Listener::open(window, state.vosk_asr.clone(), &vad, false).await?;
+            continue;
+        }
+
+        let _ = window.emit("talk_state", "thinking");
+        let user_message = append_user_message(state, conversation_id, text).await?;
+        let _ = window.emit("talk_message", &user_message);
+        let reply = start_generation(window.clone(), state, conversation_id).await?;
+        let reply = tokio::select! {
+            _ = &mut *stop => return Ok(()),
+            reply = reply => reply.map_err(|_| "生成任务意外结束".to_string())?,
+        };
+        let _ = window.emit("talk_message", &reply);
+
+        // 被打断的回复不再朗读
+        let sentences = if reply.partial {
+            Vec::new()
+        } else {
+            speech_sentences(&reply.content)
+        };
+
+        let _ = window.emit("talk_state", "speaking");
+        let end = if barge_in {
+            // 朗读的同时就开始听下一句，用户开口时停止朗读
+            listener = Listener::open(window, state.vosk_asr.clone(), &vad, true).await?;
+            let end = speak(window, &speaker, sentences, Some(&mut listener), stop).await;
+            // 没有被打断时换回正常的检测门槛听下一句
+            if matches!(end, Ok(SpeakEnd::Finished)) {
+                listener.close();
+                listener = Listener::open(window, state.vosk_asr.clone(), &vad, false).await?;
+            }
+            end
+        } else {
+            let end = speak(window, &speaker, sentences, None, stop).await;
+            listener = Listener::open(window, state.vosk_asr.clone(), &vad, false).await?;
+            end
+        };
+        match end? {
+            SpeakEnd::Finished => {}
+            SpeakEnd::Interrupted => info!("用户打断了朗读"),
+            SpeakEnd::Stopped => {
+                listener.close();
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// 逐句合成并朗读回复，播放当前句子时合成下一句
+async fn speak(
+    window: &Window,
+    speaker: &Speaker,
+    sentences: Vec<String>,
+    mut listener: Option<&mut Listener>,
+    stop: &mut oneshot::Receiver<()>,
+) -> Result<SpeakEnd, String> {
+    let mut sentences = sentences.into_iter();
+    let mut next = sentences.next().map(|text| spawn_synthesis(speaker, text));
+
+    while let Some(mut synthesis) = next.take() {
+        let path = tokio::select! {
+            _ = &mut *stop => {
+                discard_synthesis(synthesis);
+                return Ok(SpeakEnd::Stopped);
+            }
+            heard = wait_for_speech(window, &mut listener) => {
+                discard_synthesis(synthesis);
+                heard?;
+                return Ok(SpeakEnd::Interrupted);
+            }
+            path = &mut synthesis => path.map_err(|e| format!("语音合成任务异常退出: {}", e))??,
+        };
+
+        next = sentences.next().map(|text| spawn_synthesis(speaker, text));
+        let mut playback = Playback::start(path)?;
+        let end = tokio::select! {
+            _ = &mut *stop => Some(SpeakEnd::Stopped),
+            heard = wait_for_speech(window, &mut listener) => {
+                heard?;
+                Some(SpeakEnd::Interrupted)
+            }
+            result = playback.finished() => {
+                result?;
+                None
+            }
+        };
+        if let Some(end) = end {
+            playback.stop();
+            if let Some(synthesis) = next.take() {
+                discard_synthesis(synthesis);
+            }
+            return Ok(end);
+        }
+    }
+    Ok(SpeakEnd::Finished)
+}
+
+fn spawn_synthesis(speaker: &Speaker, text: String) -> JoinHandle<Result<PathBuf, String>> {
+    let speaker = speaker.clone();
+    tokio::spawn(async move { speaker.synthesize(&text).await })
+}
+
+// 不再播放的句子合成完后删除文件
+fn discard_synthesis(synthesis: JoinHandle<Result<PathBuf, String>>) {
+    tokio::spawn(async move {
+        if let Ok(Ok(path)) = synthesis.await {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("删除临时音频文件 {:?} 失败: {}", path, e);
+            }
+        }
+    });
+}
+
+// 等待用户开口，不检测打断时一直等待
+async fn wait_for_speech(
+    window: &Window,
+    listener: &mut Option<&mut Listener>,
+) -> Result<(), String> {
+    let Some(listener) = listener else {
+        return std::future::pending().await;
+    };
+    loop {
+        match listener.stream.next().await {
+            Some(Ok(text)) => {
+                if listener.handle_text(window, text) {
+                    return Ok(());
+                }
+            }
+            Some(Err(e)) => return Err(format!("语音识别出错: {}", e)),
+            None => return Ok(()),
+        }
+    }
+}
+
+/// 一次录音，录音期间持有识别器，其他语音任务需要等待
+struct Listener {
+    asr: OwnedMutexGuard<VoskASR>,
+    stream: VoskStream,
+    transcript: String,
+}
+
+impl Listener {
+    /// 打开麦克风，`barge_in` 为 true 时用于在朗读期间检测打断
+    async fn open(
+        window: &Window,
+        vosk_asr: Arc<tokio::sync::Mutex<VoskASR>>,
+        vad: &VadConfig,
+        barge_in: bool,
+    ) -> Result<Self, String> {
+        let mut asr = vosk_asr.lock_owned().await;
+        if barge_in {
+            asr.set_vad_config(VadConfig {
+                threshold_db: vad.threshold_db + BARGE_IN_EXTRA_THRESHOLD_DB,
+                min_speech_ms: vad.min_speech_ms.max(BARGE_IN_MIN_SPEECH_MS),
+                ..vad.clone()
+            });
+        }
+        let level_window = window.clone();
+        // 对话模式不设超时，一直听到用户说完一句话
+        let stream = asr
+            .listen_and_transcribe(None, move |level: f32| {
+                let _ = level_window.emit("voice_level", level);
+            })
+            .await;
+        // 检测参数在开始录音时已经复制，恢复后不影响本次录音
+        if barge_in {
+            asr.set_vad_config(vad.clone());
+        }
+        let stream = stream.map_err(|e| format!("创建语音输入流失败: {}", e))?;
+        Ok(Self {
+            asr,
+            stream,
+            transcript: String::new(),
+        })
+    }
+
+    /// 听到一句话结束，返回识别的文本，收到停止请求时返回 None
+    async fn listen(
+        &mut self,
+        window: &Window,
+        stop: &mut oneshot::Receiver<()>,
+    ) -> Result<Option<String>, String> {
+        loop {
+            let result = tokio::select! {
+                _ = &mut *stop => return Ok(None),
+                result = self.stream.next() => result,
+            };
+            match result {
+                Some(Ok(text)) => {
+                    // 还没说话时的静音不算一句话结束
+                    let finished = text == "[silence detected]" && !self.transcript.is_empty();
+                    self.handle_text(window, text);
+                    if finished {
+                        break;
+                    }
+                }
+                Some(Err(e)) => return Err(format!("语音识别出错: {}", e)),
+                None => break,
+            }
+        }
+        Ok(Some(std::mem::take(&mut self.transcript)))
+    }
+
+    // 转发识别结果并记录最新的文本，返回用户是否已经开口
+    fn handle_text(&mut self, window: &Window, text: String) -> bool {
+        // 特殊标记不发给前端
+        if text.starts_with('[') && text.ends_with(']') {
+            debug!("录音事件: {}", text);
+            return text == "[speech started]";
+        }
+        if text.trim().is_empty() {
+            return false;
+        }
+        let _ = window.emit("talk_partial", &text);
+        self.transcript = text;
+        true
+    }
+
+    // 停止录音并释放识别器
+    fn close(mut self) {
+        if let Err(e) = self.asr.stop_recording() {
+            error!("停止录音失败: {:?}", e);
+        }
+    }
+}
+
+// 移除本次对话模式的登记（可能已被新的对话模式替换）
+fn remove_session(sessions: &Mutex<Option<TalkSession>>, id: u64) {
+    let mut guard = sessions.lock().unwrap();
+    if guard.as_ref().is_some_and(|session| session.id == id) {
+        *guard = None;
+    }
+}
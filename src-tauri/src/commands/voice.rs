@@ -1,127 +1,270 @@
-use crate::state::AppState;
-use log::{debug, error, info};
+use crate::commands::talk::stop_talk_session;
+use crate::models::{AudioInputDevice, Transcription};
+use crate::services::asr::devices::input_devices;
+use crate::services::asr::VoskASR;
+use crate::state::{AppState, VoiceControl, VoiceSession};
+use log::{debug, error, info, warn};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tauri::{Emitter, State, Window};
+use tokio::sync::oneshot;
 use tokio_stream::StreamExt;
 
+// 停止录音后等待最后一句识别结果的时间
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 语音输入任务的结束方式
+enum SessionEnd {
+    /// 超时或识别流自然结束
+    Finished,
+    /// 用户停止录音，需要把文本返回给调用方
+    Stopped(oneshot::Sender<Result<String, String>>),
+    Cancelled,
+    Failed(String),
+}
+
+/// 列出可用的麦克风，选中的设备保存在 `VoiceConfig.input_device` 中
+#[tauri::command]
+pub fn list_input_devices() -> Result<Vec<AudioInputDevice>, String> {
+    input_devices()
+}
+
+/// 开始语音输入，识别过程中发送 `voice_partial` 和 `voice_level` 事件，录音开始后立即返回
+///
+/// 录音在 `stop_voice_input`、`cancel_voice_input` 或达到配置的超时时间后结束，
+/// 已有的语音输入会先被取消，进行中的对话模式会被停止
 #[tauri::command]
-pub async fn voice_input(
+pub async fn start_voice_input(
     window: Window,
     conversation_id: u64,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<(), String> {
     info!("开始语音输入，对话ID: {}", conversation_id);
+    let timeout_secs = state.config.lock().unwrap().voice.timeout_seconds;
+
+    // 登记本次录音，同一时间只保留一个
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst);
+    let (control_tx, control_rx) = oneshot::channel();
+    let previous = state.voice_session.lock().unwrap().replace(VoiceSession {
+        id,
+        conversation_id,
+        control: control_tx,
+    });
+    if let Some(previous) = previous {
+        info!(
+            "对话 {} 有未结束的语音输入，先取消",
+            previous.conversation_id
+        );
+        let _ = previous.control.send(VoiceControl::Cancel);
+    }
+    // 对话模式也在使用麦克风
+    stop_talk_session(&state);
 
     // 通知前端录音开始
     window
         .emit("voice_status", "recording")
         .map_err(|e| e.to_string())?;
-    debug!("已通知前端录音");
 
-    info!("开始语音输入，对话ID: {}", conversation_id);
+    let (ready_tx, ready_rx) = oneshot::channel();
+    tokio::spawn(run_session(
+        window,
+        state.vosk_asr.clone(),
+        state.voice_session.clone(),
+        id,
+        timeout_secs,
+        control_rx,
+        ready_tx,
+    ));
 
-    window
-        .emit("voice_status", "recording")
-        .map_err(|e| e.to_string())?;
+    // 等待麦克风打开，打不开时把错误返回给前端
+    ready_rx
+        .await
+        .map_err(|_| "语音识别任务意外结束".to_string())?
+}
+
+/// 停止语音输入并返回最终识别的文本
+#[tauri::command]
+pub async fn stop_voice_input(state: State<'_, AppState>) -> Result<String, String> {
+    let session = state
+        .voice_session
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| "没有正在进行的语音输入".to_string())?;
+    info!("停止对话 {} 的语音输入", session.conversation_id);
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    session
+        .control
+        .send(VoiceControl::Stop(reply_tx))
+        .map_err(|_| "语音输入已结束".to_string())?;
+    reply_rx.await.map_err(|_| "语音输入已结束".to_string())?
+}
+
+/// 取消语音输入，丢弃识别结果
+#[tauri::command]
+pub fn cancel_voice_input(state: State<AppState>) -> Result<bool, String> {
+    let session = state.voice_session.lock().unwrap().take();
+    match session {
+        Some(session) => {
+            info!("取消对话 {} 的语音输入", session.conversation_id);
+            // 任务可能恰好已经结束，发送失败可以忽略
+            let _ = session.control.send(VoiceControl::Cancel);
+            Ok(true)
+        }
+        None => {
+            debug!("没有正在进行的语音输入");
+            Ok(false)
+        }
+    }
+}
+
+/// 转写音频文件，识别过程中和录音一样发送 `voice_partial` 事件
+#[tauri::command]
+pub async fn transcribe_file(
+    window: Window,
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<Transcription, String> {
+    info!("开始转写音频文件: {}", path);
 
-    let (tx, rx) = tokio::sync::oneshot::channel::<Result<String, String>>();
+    // 正在录音时等待录音结束
+    let mut vosk_asr = state.vosk_asr.lock().await;
+    let transcription = vosk_asr
+        .transcribe_file(Path::new(&path), move |text| {
+            let _ = window.emit("voice_partial", text);
+        })
+        .await
+        .map_err(|e| {
+            error!("转写音频文件失败: {}", e);
+            e
+        })?;
 
-    // 提前获取锁并立即释放（关键修改1）
-    let vosk_asr = state.vosk_asr.clone();
+    info!("音频文件转写完成: {}", path);
+    Ok(transcription)
+}
 
-    let window_clone1 = window.clone();
-    let window_clone2 = window.clone();
+async fn run_session(
+    window: Window,
+    vosk_asr: Arc<tokio::sync::Mutex<VoskASR>>,
+    sessions: Arc<Mutex<Option<VoiceSession>>>,
+    id: u64,
+    timeout_secs: u64,
+    mut control: oneshot::Receiver<VoiceControl>,
+    ready: oneshot::Sender<Result<(), String>>,
+) {
+    // 上一次录音被取消后可能还没有释放识别器
+    let mut vosk_asr = vosk_asr.lock().await;
+    let _ = window.emit("voice_partial", "[booting]");
 
-    // 在单独的任务中处理语音识别，避免跨线程共享VoskASR实例
-    tokio::spawn(async move {
-        let mut vosk_asr = vosk_asr.lock().await;
+    // 音量用于前端显示实时的音量条
+    let level_window = window.clone();
+    let on_level = move |level: f32| {
+        let _ = level_window.emit("voice_level", level);
+    };
+    let mut stream = match vosk_asr
+        .listen_and_transcribe(Some(timeout_secs), on_level)
+        .await
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("创建语音输入流失败: {}", e);
+            remove_session(&sessions, id);
+            let _ = window.emit("voice_status", "error");
+            let _ = ready.send(Err(format!("创建语音输入流失败: {}", e)));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+    debug!("成功创建语音输入流");
 
-        // 使用独立作用域限制锁的生命周期
-        {
-            let _ = window_clone1.emit("voice_partial", "[booting]");
+    let mut transcript = String::new();
+    let end = loop {
+        let result = tokio::select! {
+            request = &mut control => match request {
+                Ok(VoiceControl::Stop(reply)) => break SessionEnd::Stopped(reply),
+                // 登记被移除时视为取消
+                Ok(VoiceControl::Cancel) | Err(_) => break SessionEnd::Cancelled,
+            },
+            result = stream.next() => result,
+        };
+        match result {
+            Some(Ok(text)) => handle_text(&window, &mut transcript, text),
+            Some(Err(e)) => {
+                error!("语音识别错误: {}", e);
+                break SessionEnd::Failed(format!("语音识别出错: {}", e));
+            }
+            None => break SessionEnd::Finished,
         }
+    };
 
-        // 创建流时获取锁并立即释放
-        let mut stream = match vosk_asr.listen_and_transcribe(Some(15)).await {
-            Ok(s) => s,
-            Err(e) => {
-                error!("创建语音输入流失败: {}", e);
-                let _ = window_clone1.emit("voice_status", "error");
-                let _ = tx.send(Err(format!("创建语音输入流失败: {}", e)));
-                return;
+    // 停止录音后，识别器还会给出最后一句的结果
+    if let Err(e) = vosk_asr.stop_recording() {
+        error!("停止录音失败: {:?}", e);
+    }
+    if let SessionEnd::Stopped(_) = end {
+        let drain = async {
+            while let Some(Ok(text)) = stream.next().await {
+                handle_text(&window, &mut transcript, text);
             }
         };
-        let mut final_transcript = String::new();
-
-        // 在单独作用域中锁定vosk_asr，确保锁被及时释放
-        {
-            let _ = window_clone1.emit("voice_partial", "[booting]");
-
-            debug!("成功创建语音输入流");
-
-            while let Some(result) = stream.next().await {
-                match result {
-                    Ok(text) => {
-                        // 特殊标记处理
-                        if text == "[timeout reached]" || text == "[silence detected]" {
-                            info!("录音事件: {}", text);
-                            debug!("特殊标记: {}", text);
-                            let _ = window_clone1.emit("voice_partial", &text);
-
-                            // 如果是超时，则结束流程
-                            if text == "[timeout reached]" {
-                                info!("因超时结束录音");
-                                break;
-                            }
-                            continue;
-                        }
-
-                        // 发送部分结果到前端
-                        let _ = window_clone1.emit("voice_partial", &text);
-
-                        // 更新最终文本（仅当不为空时）
-                        if !text.trim().is_empty() {
-                            final_transcript = text;
-                        }
-                    }
-                    Err(e) => {
-                        error!("语音识别错误: {}", e);
-                        let _ = window_clone1.emit("voice_status", "error");
-                        let _ = tx.send(Err(format!("语音识别出错: {}", e)));
-                        return;
-                    }
-                }
-            }
+        if tokio::time::timeout(STOP_TIMEOUT, drain).await.is_err() {
+            warn!("等待最终识别结果超时");
+        }
+    }
+    drop(stream);
+    drop(vosk_asr);
+    remove_session(&sessions, id);
 
-            // 显式停止录音
-            debug!("流处理完成，停止录音");
-            {
-                // let mut vosk_asr = vosk_asr.lock().unwrap();
-                if let Err(e) = vosk_asr.stop_recording() {
-                    error!("停止录音失败: {:?}", e);
-                }
+    match end {
+        SessionEnd::Finished => {
+            info!("语音输入结束");
+            let _ = window.emit("voice_status", "completed");
+            // 结束的同时可能收到了停止请求
+            if let Ok(VoiceControl::Stop(reply)) = control.try_recv() {
+                let _ = reply.send(Ok(transcript));
             }
         }
+        SessionEnd::Stopped(reply) => {
+            info!("语音输入已停止");
+            let _ = window.emit("voice_status", "completed");
+            let _ = reply.send(Ok(transcript));
+        }
+        SessionEnd::Cancelled => {
+            info!("语音输入已取消");
+            let _ = window.emit("voice_status", "cancelled");
+        }
+        SessionEnd::Failed(e) => {
+            let _ = window.emit("voice_status", "error");
+            if let Ok(VoiceControl::Stop(reply)) = control.try_recv() {
+                let _ = reply.send(Err(e));
+            }
+        }
+    }
+}
 
-        debug!("语音识别任务完成");
-        // 通知前端录音完成
-        let _ = window_clone1.emit("voice_status", "completed");
+// 转发识别结果，并记录最新的非空文本
+fn handle_text(window: &Window, transcript: &mut String, text: String) {
+    let _ = window.emit("voice_partial", &text);
 
-        // 发送最终结果
-        let _ = tx.send(Ok(final_transcript));
-    });
+    // 特殊标记只通知前端
+    if text.starts_with('[') && text.ends_with(']') {
+        info!("录音事件: {}", text);
+        return;
+    }
+    if !text.trim().is_empty() {
+        *transcript = text;
+    }
+}
 
-    // 等待任务完成，获取结果
-    match rx.await {
-        Ok(result) => {
-            debug!("语音输入完成，返回结果");
-            result
-        }
-        Err(e) => {
-            error!("等待语音识别结果失败: {}", e);
-            window_clone2
-                .emit("voice_status", "error")
-                .map_err(|e| e.to_string())?;
-            Err(format!("等待语音识别结果失败: {}", e))
-        }
+// 移除本次录音的登记（可能已被新的录音替换）
+fn remove_session(sessions: &Mutex<Option<VoiceSession>>, id: u64) {
+    let mut guard = sessions.lock().unwrap();
+    if guard.as_ref().is_some_and(|session| session.id == id) {
+        *guard = None;
     }
 }
@@ -7,17 +7,14 @@ mod services;
 mod state;
 mod utils;
 
-use chrono::Utc;
 use log::{error, info};
-use models::{Conversation, Message};
-use services::agent::ollama::OllamaAgent;
-use services::asr::vosk_python::VoskASR;
+use services::agent::create_backend;
+use services::asr::create_vosk_asr;
 use state::AppState;
-use std::path::Path;
 use tauri::path::BaseDirectory;
 use tauri::Manager;
-use utils::config::{get_app_config, save_app_config, AppConfig};
-use utils::logger::init_logger; // 导入配置相关函数
+use utils::config::{get_app_config, open_database, save_app_config, AppConfig};
+use utils::logger::{apply_log_level, init_logger}; // 导入配置相关函数
 
 // 导入所有命令
 use commands::*;
@@ -43,18 +40,49 @@ async fn main() {
             get_conversation_messages,
             create_conversation,
             delete_conversation,
+            rename_conversation,
+            set_conversation_pinned,
+            set_conversation_archived,
+            set_conversation_tags,
+            get_conversation_tags,
+            get_conversation_settings,
+            update_conversation_settings,
             // 消息相关命令
             send_user_message,
+            edit_user_message,
+            switch_branch,
+            get_message_branches,
             // AI相关命令
             generate_ai_response,
+            regenerate_response,
+            stop_generation,
+            // 模型管理命令
+            list_models,
+            pull_model,
+            delete_model,
+            show_model_info,
+            set_active_model,
             // 语音相关命令
-            voice_input,
+            list_input_devices,
+            start_voice_input,
+            stop_voice_input,
+            cancel_voice_input,
+            transcribe_file,
+            talk_mode_available,
+            start_talk_mode,
+            stop_talk_mode,
             // 配置相关命令
             get_app_config,
             save_app_config,
             // 数据库管理命令
+            get_database_error,
             get_database_conversations,
             delete_database_conversation,
+            search_messages,
+            // 导入导出命令
+            export_conversation,
+            export_all_conversations,
+            import_conversations,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -64,122 +92,59 @@ fn init_config(handle: tauri::AppHandle) -> Result<AppState, std::io::Error> {
     // TODO:添加资源检查
     // check_resource(handle.path().resource_dir())
     //     .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-    // 获取设置目录
+    // 先初始化日志，记录配置加载和迁移过程
+    init_logger();
+
+    // 用户配置保存在应用配置目录，安装包自带的配置作为默认值
     let config_path = handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
+        .join("config.yaml");
+    let bundled_config_path = handle
         .path()
         .resolve("config.yaml", BaseDirectory::Resource)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+        .ok();
     // 加载配置
-    let config = AppConfig::new(config_path.clone()).load_config();
+    let config = AppConfig::new(config_path).load_config(bundled_config_path.as_deref());
 
     // 设置日志级别（从配置中读取）
-    init_logger();
+    apply_log_level(&config.app_behavior.log_level);
     info!("应用启动，配置加载完成");
 
-    // 创建OllamaAgent实例（使用配置中的值）
-    let ollama_agent = OllamaAgent::new(
-        &config.ai_model.model_name,
-        &config.ai_model.server_url,
-        &config.ai_model.server_port,
-    )
-    .with_system_prompt(&config.ai_model.system_prompt);
+    // 根据配置中的 provider 创建模型后端
+    let agent = create_backend(&config.ai_model)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
 
-    info!("OllamaAgent initialized");
+    info!("{} backend initialized", agent.provider());
 
     // 创建Vosk ASR实例（使用配置中的值）
-    let vosk_asr = if config.voice.enabled {
-        let model_path = if Path::new(&config.voice.model_path).is_absolute() {
-            config.voice.model_path.clone()
-        } else {
-            // 使用相对路径，根据应用资源目录解析
-            handle
-                .path()
-                .resolve(&config.voice.model_path, BaseDirectory::Resource)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
-                .to_string_lossy()
-                .to_string()
-        };
-
-        match VoskASR::new(Some(&model_path)) {
-            Ok(asr) => asr,
-            Err(e) => {
-                error!("VoskASR initialization failed: {}", e);
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e.to_string(),
-                ));
-            }
-        }
-    } else {
-        let vosk_model_path = handle
-            .path()
-            .resolve("model/vosk-model-small-cn-0.22", BaseDirectory::Resource)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
-            .to_string_lossy()
-            .to_string();
-        info!("Vosk model path: {:?}", vosk_model_path);
-        match VoskASR::new(Some(&vosk_model_path)) {
-            Ok(asr) => asr,
-            Err(e) => {
-                error!("VoskASR initialization failed: {}", e);
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e.to_string(),
-                ));
-            }
+    let vosk_asr = create_vosk_asr(&handle, &config.voice)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+    let state = AppState::new(config.clone(), agent, vosk_asr);
+
+    // 初始化数据库，未启用时使用内存数据库。打开或升级失败时不能改用内存数据库，
+    // 否则用户看不到历史记录，输入的内容在退出后也会丢失；记录错误由前端提示用户
+    let db = match open_database(&handle, &config.database) {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            state.set_database_error(Some(e));
+            return Ok(state);
         }
     };
-
-    let default_conversation_id = 1;
-
-    // 初始化应用状态（使用配置中的值）
-    let conversations = vec![Conversation {
-        id: default_conversation_id,
-        title: config.app_behavior.default_conversation_title.clone(),
-        last_message: "你好!".to_string(),
-        timestamp: Utc::now().timestamp_millis() as u64,
-    }];
-
-    let messages = vec![Message {
-        id: 1,
-        content: config.app_behavior.welcome_message.clone(),
-        sender: "bot".to_string(),
-        timestamp: Utc::now().timestamp_millis() as u64,
-        conversation_id: default_conversation_id,
-    }];
-
-    let state = AppState::new(
-        config.clone(),
-        conversations,
-        messages,
-        ollama_agent,
-        vosk_asr,
-    );
-
-    // 初始化数据库
-    if config.database.enabled {
-        let db_path = if Path::new(&config.database.path).is_absolute() {
-            config.database.path.clone()
-        } else {
-            handle
-                .path()
-                .resolve(&config.database.path, BaseDirectory::Resource)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
-                .to_string_lossy()
-                .to_string()
-        };
-        if let Some(parent) = Path::new(&db_path).parent() {
-            std::fs::create_dir_all(parent).expect("无法创建数据库目录");
-        }
-        if let Err(e) = state.init_database(&db_path) {
-            error!("数据库初始化失败: {}", e);
-        } else {
-            // 从数据库加载对话和消息
-            if let Err(e) = state.load_from_database() {
-                error!("从数据库加载数据失败: {}", e);
-            }
-        }
+    match db.write_blocking(|db| {
+        db.ensure_default_conversation(
+            &config.app_behavior.default_conversation_title,
+            &config.app_behavior.welcome_message,
+        )
+    }) {
+        Ok(Some(id)) => info!("创建了默认对话 {}", id),
+        Ok(None) => {}
+        Err(e) => error!("创建默认对话失败: {}", e),
     }
+    state.replace_database(Some(db));
 
     Ok(state)
 }
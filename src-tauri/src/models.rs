@@ -7,6 +7,12 @@ pub struct Message {
     pub sender: String,
     pub timestamp: u64,
     pub conversation_id: u64,
+    /// 生成被中断时为 true，内容不完整
+    #[serde(default)]
+    pub partial: bool,
+    /// 上一条消息，编辑或重新生成时同一条消息下会有多个分支
+    #[serde(default)]
+    pub parent_id: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,6 +21,60 @@ pub struct Conversation {
     pub title: String,
     pub last_message: String,
     pub timestamp: u64,
+    /// 置顶的对话排在列表最前面
+    #[serde(default)]
+    pub pinned: bool,
+    /// 归档的对话默认不在列表中显示
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// 对话列表的排序方式，置顶的对话总是排在最前面
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversationSort {
+    /// 按最后活动时间，最近的在前
+    #[default]
+    Recent,
+    /// 按创建顺序，最新的在前
+    Created,
+    /// 按标题字母顺序
+    Title,
+}
+
+/// 获取对话列表时的筛选条件
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ConversationFilter {
+    /// 为 true 时只返回已归档的对话，否则只返回未归档的
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub pinned_only: bool,
+    /// 只返回带有全部这些标签的对话
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub sort: ConversationSort,
+}
+
+/// 对话级别的模型设置，为空的字段使用全局配置
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ConversationSettings {
+    pub conversation_id: u64,
+    #[serde(default)]
+    pub model_name: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub num_ctx: Option<u64>,
+    #[serde(default)]
+    pub seed: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,4 +82,95 @@ pub struct MessageChunk {
     pub conversation_id: u64,
     pub content: String,
     pub is_complete: bool,
+    /// 生成被用户中止时为 true，仅随最后一个块发送
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+/// AI 生成失败时发送给前端的事件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageError {
+    pub conversation_id: u64,
+    /// 机器人消息的ID，创建回复之前就失败时为空
+    pub message_id: Option<u64>,
+    /// 错误类型: connection_refused / connection_lost / model_not_found / context_overflow / server_error / unsupported
+    pub kind: String,
+    pub message: String,
+    /// 重试是否可能成功
+    pub retryable: bool,
+}
+
+/// 消息写入数据库失败时发送给前端的事件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessagePersistError {
+    pub conversation_id: u64,
+    pub message_id: u64,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// 当前分支上有多个版本的消息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageBranch {
+    pub message_id: u64,
+    /// 同一条上级消息下的所有版本，按创建顺序排列，包含 message_id 本身
+    pub siblings: Vec<u64>,
+}
+
+/// 可用于录音的麦克风
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioInputDevice {
+    pub name: String,
+    /// 是否为系统默认的输入设备
+    pub is_default: bool,
+}
+
+/// 转写音频文件时识别出的一个词
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WordTiming {
+    pub word: String,
+    /// 在音频中的开始和结束时间，单位为秒
+    pub start: f32,
+    pub end: f32,
+    /// 识别置信度，0 到 1
+    pub confidence: f32,
+}
+
+/// 音频文件的转写结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Transcription {
+    pub text: String,
+    pub words: Vec<WordTiming>,
+}
+
+/// 全文搜索命中的消息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub message_id: u64,
+    pub conversation_id: u64,
+    pub conversation_title: String,
+    /// 消息摘要，原文已做 HTML 转义，匹配部分用 <mark></mark> 包裹，可以直接作为 HTML 渲染
+    pub snippet: String,
+    pub sender: String,
+    pub timestamp: u64,
+}
+
+/// 导入时被跳过的对话或消息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SkippedItem {
+    /// 所属对话的标题
+    pub conversation: String,
+    /// 来源文件中的消息ID，整个对话被跳过时为空
+    pub message: Option<String>,
+    pub reason: String,
+}
+
+/// 导入结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportReport {
+    /// 识别出的文件来源: chat_box / chatgpt / open_webui
+    pub source: String,
+    pub conversations: usize,
+    pub messages: usize,
+    pub skipped: Vec<SkippedItem>,
 }
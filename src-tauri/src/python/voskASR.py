@@ -15,8 +15,8 @@ logging.basicConfig(level=logging.DEBUG,
 logger = logging.getLogger(__name__)
 
 class VoskRecognizer:
-    def __init__(self, model_path=None):
-        logger.info(f"初始化VoskRecognizer，模型路径：{model_path}")
+    def __init__(self, model_path=None, device_name=None):
+        logger.info(f"初始化VoskRecognizer，模型路径：{model_path}，麦克风：{device_name or '默认'}")
         if model_path:
             self.model = vosk.Model(model_path)
         else:
@@ -25,6 +25,7 @@ class VoskRecognizer:
 
         self.recognizer = vosk.KaldiRecognizer(self.model, 16000)
         self.audio = pyaudio.PyAudio()
+        self.device_index = self._find_device(device_name)
         self.stream = None
         self.running = False
         self.result_queue = queue.Queue()
@@ -32,6 +33,17 @@ class VoskRecognizer:
         self._lock = threading.Lock()  # 添加锁以保护关键区域
         logger.info("VoskRecognizer初始化完成")
 
+    def _find_device(self, device_name):
+        """按名称查找输入设备，找不到时返回 None 使用默认设备"""
+        if not device_name:
+            return None
+        for index in range(self.audio.get_device_count()):
+            info = self.audio.get_device_info_by_index(index)
+            if info.get("maxInputChannels", 0) > 0 and device_name in info.get("name", ""):
+                return index
+        logger.warning(f"找不到麦克风 {device_name}，改用默认设备")
+        return None
+
     def start_stream(self):
         """启动音频流但不开始识别"""
         with self._lock:
@@ -46,6 +58,7 @@ class VoskRecognizer:
                     channels=1,
                     rate=16000,
                     input=True,
+                    input_device_index=self.device_index,
                     frames_per_buffer=8000
                 )
                 self.running = True
@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// 调用模型后端时可能出现的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentError {
+    /// 无法连接到模型服务（服务未启动、地址或端口错误）
+    ConnectionRefused(String),
+    /// 生成过程中连接中断
+    ConnectionLost(String),
+    /// 服务端不存在该模型
+    ModelNotFound(String),
+    /// 对话内容超出模型上下文长度
+    ContextOverflow(String),
+    /// 服务端返回的其他错误
+    Server(String),
+    /// 当前后端不支持该操作
+    Unsupported(String),
+}
+
+impl AgentError {
+    /// 错误类型标识，随 `message_error` 事件发送给前端
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AgentError::ConnectionRefused(_) => "connection_refused",
+            AgentError::ConnectionLost(_) => "connection_lost",
+            AgentError::ModelNotFound(_) => "model_not_found",
+            AgentError::ContextOverflow(_) => "context_overflow",
+            AgentError::Server(_) => "server_error",
+            AgentError::Unsupported(_) => "unsupported",
+        }
+    }
+
+    /// 用户重试是否有可能成功
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            AgentError::ModelNotFound(_)
+                | AgentError::ContextOverflow(_)
+                | AgentError::Unsupported(_)
+        )
+    }
+
+    /// 根据服务端返回的错误信息判断错误类型
+    pub fn from_server_message(model: &str, status: Option<u16>, message: &str) -> Self {
+        let message = Self::extract_error_message(message);
+        let lower = message.to_lowercase();
+
+        if lower.contains("not found") && (lower.contains("model") || status == Some(404)) {
+            AgentError::ModelNotFound(model.to_string())
+        } else if lower.contains("context")
+            && (lower.contains("length")
+                || lower.contains("exceed")
+                || lower.contains("overflow")
+                || lower.contains("too long")
+                || lower.contains("size"))
+        {
+            AgentError::ContextOverflow(message)
+        } else {
+            match status {
+                Some(status) => AgentError::Server(format!("{}: {}", status, message)),
+                None => AgentError::Server(message),
+            }
+        }
+    }
+
+    /// 服务端的错误通常是 `{"error": "..."}` 或 `{"error": {"message": "..."}}`
+    fn extract_error_message(body: &str) -> String {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(body) {
+            let error = &value["error"];
+            if let Some(message) = error.as_str() {
+                return message.to_string();
+            }
+            if let Some(message) = error["message"].as_str() {
+                return message.to_string();
+            }
+        }
+        body.trim().to_string()
+    }
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentError::ConnectionRefused(e) => write!(f, "无法连接到模型服务: {}", e),
+            AgentError::ConnectionLost(e) => write!(f, "与模型服务的连接中断: {}", e),
+            AgentError::ModelNotFound(model) => write!(f, "模型 {} 不存在，请先下载该模型", model),
+            AgentError::ContextOverflow(e) => write!(f, "对话内容超出模型上下文长度: {}", e),
+            AgentError::Server(e) => write!(f, "模型服务返回错误: {}", e),
+            AgentError::Unsupported(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+impl From<reqwest::Error> for AgentError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_connect() || e.is_timeout() {
+            AgentError::ConnectionRefused(e.to_string())
+        } else if e.is_body() || e.is_decode() {
+            AgentError::ConnectionLost(e.to_string())
+        } else {
+            AgentError::Server(e.to_string())
+        }
+    }
+}
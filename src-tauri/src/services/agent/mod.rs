@@ -1 +1,202 @@
+mod error;
 pub(crate) mod ollama;
+pub(crate) mod openai;
+
+pub use error::AgentError;
+
+use crate::models::{ConversationSettings, Message};
+use crate::utils::config::AiModelConfig;
+use futures::future::BoxFuture;
+use log::warn;
+use ollama::OllamaAgent;
+use openai::OpenAiAgent;
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::Stream;
+
+/// 模型回复的增量文本流，出错时产生一个 `Err` 后结束
+pub type ChatStream = Pin<Box<dyn Stream<Item = Result<String, AgentError>> + Send>>;
+
+/// 下载模型的进度流
+pub type PullStream = Pin<Box<dyn Stream<Item = Result<PullProgress, AgentError>> + Send>>;
+
+/// 单次生成的参数，未设置的字段使用后端的默认值
+#[derive(Debug, Clone, Default)]
+pub struct ChatOptions {
+    /// 覆盖后端当前的模型
+    pub model: Option<String>,
+    /// 覆盖后端的系统提示词
+    pub system_prompt: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// 上下文长度，OpenAI 兼容接口没有对应参数
+    pub num_ctx: Option<u64>,
+    pub seed: Option<i32>,
+}
+
+impl From<ConversationSettings> for ChatOptions {
+    fn from(settings: ConversationSettings) -> Self {
+        Self {
+            model: settings.model_name,
+            system_prompt: settings.system_prompt,
+            temperature: settings.temperature,
+            top_p: settings.top_p,
+            num_ctx: settings.num_ctx,
+            seed: settings.seed,
+        }
+    }
+}
+
+/// 服务端的一个模型
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelSummary {
+    pub name: String,
+    /// 模型文件大小（字节），服务端不提供时为空
+    pub size: Option<u64>,
+    pub modified_at: Option<String>,
+}
+
+/// 模型详细信息
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelDetails {
+    pub name: String,
+    pub parameters: String,
+    pub template: String,
+    pub context_length: Option<u64>,
+    pub capabilities: Vec<String>,
+    pub license: String,
+    /// 服务端返回的其他原始信息
+    pub model_info: serde_json::Map<String, serde_json::Value>,
+}
+
+/// 下载模型时的一条进度
+#[derive(Debug, Clone, Serialize)]
+pub struct PullProgress {
+    pub model: String,
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
+/// 对话消息的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// 与具体后端无关的一条对话消息
+#[derive(Debug, Clone)]
+pub struct ChatTurn {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+/// 聊天后端接口，所有大模型服务（Ollama、OpenAI 兼容服务等）都通过它接入
+pub trait ChatBackend: Send + Sync {
+    /// 后端名称，对应配置中的 `provider`
+    fn provider(&self) -> &'static str;
+
+    /// 当前使用的模型名称
+    fn model(&self) -> String;
+
+    /// 运行时切换模型，之后的生成都使用新模型
+    fn set_model(&self, model: &str);
+
+    /// 根据对话历史流式生成回复
+    ///
+    /// 丢弃返回的流即取消本次生成：流持有底层的 HTTP 响应，丢弃后连接随之关闭，
+    /// 服务端会停止生成。各次生成互不影响，取消一次生成不会中断其他对话
+    fn chat_stream<'a>(
+        &'a self,
+        history: &'a [Message],
+        options: &'a ChatOptions,
+    ) -> BoxFuture<'a, Result<ChatStream, AgentError>>;
+
+    /// 列出服务端可用的模型
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<ModelSummary>, AgentError>>;
+
+    /// 下载模型，返回进度流
+    fn pull_model<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<PullStream, AgentError>> {
+        Box::pin(async move {
+            Err(AgentError::Unsupported(format!(
+                "{} 不支持下载模型 {}",
+                self.provider(),
+                name
+            )))
+        })
+    }
+
+    /// 删除本地模型
+    fn delete_model<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), AgentError>> {
+        Box::pin(async move {
+            Err(AgentError::Unsupported(format!(
+                "{} 不支持删除模型 {}",
+                self.provider(),
+                name
+            )))
+        })
+    }
+
+    /// 查看模型详细信息
+    fn show_model<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<ModelDetails, AgentError>> {
+        Box::pin(async move {
+            Err(AgentError::Unsupported(format!(
+                "{} 不支持查看模型 {} 的信息",
+                self.provider(),
+                name
+            )))
+        })
+    }
+}
+
+/// 根据配置中的 `provider` 创建对应的后端
+pub fn create_backend(config: &AiModelConfig) -> Result<Arc<dyn ChatBackend>, String> {
+    match config.provider.as_str() {
+        "ollama" => Ok(Arc::new(
+            OllamaAgent::new(&config.model_name, &config.server_url, &config.server_port)
+                .with_system_prompt(&config.system_prompt),
+        )),
+        "openai" => Ok(Arc::new(
+            OpenAiAgent::new(&config.model_name, &config.server_url, &config.server_port)
+                .with_system_prompt(&config.system_prompt)
+                .with_api_key(&config.api_key),
+        )),
+        other => Err(format!("不支持的模型服务提供方: {}", other)),
+    }
+}
+
+/// 将系统提示词和对话历史整理为带角色的消息列表
+pub fn build_chat_turns(system_prompt: &str, history: &[Message]) -> Vec<ChatTurn> {
+    let mut turns = Vec::with_capacity(history.len() + 1);
+    if !system_prompt.trim().is_empty() {
+        turns.push(ChatTurn {
+            role: ChatRole::System,
+            content: system_prompt.to_string(),
+        });
+    }
+
+    for msg in history {
+        // 跳过还没有内容的占位消息
+        if msg.content.trim().is_empty() {
+            continue;
+        }
+        let role = match msg.sender.as_str() {
+            "user" => ChatRole::User,
+            "bot" => ChatRole::Assistant,
+            other => {
+                warn!("忽略未知发送者的消息: {}", other);
+                continue;
+            }
+        };
+        turns.push(ChatTurn {
+            role,
+            content: msg.content.clone(),
+        });
+    }
+
+    turns
+}
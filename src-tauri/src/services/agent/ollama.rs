@@ -1,9 +1,24 @@
-use ollama_rs::{generation::completion::request::GenerationRequest, Ollama};
+use super::{
+    build_chat_turns, AgentError, ChatBackend, ChatOptions, ChatRole, ChatStream, ChatTurn,
+    ModelDetails, ModelSummary, PullProgress, PullStream,
+};
+use crate::models::Message;
+use futures::future::BoxFuture;
+use ollama_rs::{
+    error::OllamaError,
+    generation::{
+        chat::{request::ChatMessageRequest, ChatMessage},
+        completion::request::GenerationRequest,
+    },
+    models::ModelOptions,
+    Ollama,
+};
+use std::sync::RwLock;
 use tokio_stream::{Stream, StreamExt};
 
 #[allow(unused_variables)]
 pub struct OllamaAgent {
-    model: String,
+    model: RwLock<String>,
     host: String,
     port: u16,
     system_prompt: String,
@@ -18,7 +33,7 @@ impl OllamaAgent {
         let ollama = Ollama::new(host.clone(), port);
 
         Self {
-            model: model.to_string(),
+            model: RwLock::new(model.to_string()),
             host,
             port,
             system_prompt: "你是一个使用中文作为主要语言的问答助手。".to_string(),
@@ -38,21 +53,12 @@ impl OllamaAgent {
         self
     }
 
-    pub async fn generate_response(
-        &self,
-        user_prompt: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let full_prompt = format!("{}\n\n{}", self.system_prompt, user_prompt);
-        let request = GenerationRequest::new(self.model.clone(), full_prompt);
-
-        let mut stream = self.ollama.generate_stream(request).await?;
+    pub async fn generate_response(&self, user_prompt: &str) -> Result<String, AgentError> {
+        let mut stream = self.generate_stream(user_prompt).await?;
         let mut response_output = String::new();
 
         while let Some(res) = stream.next().await {
-            let responses = res?;
-            for resp in responses {
-                response_output.push_str(&resp.response);
-            }
+            response_output.push_str(&res?);
         }
 
         Ok(response_output)
@@ -61,20 +67,197 @@ impl OllamaAgent {
     pub async fn generate_stream(
         &self,
         user_prompt: &str,
-    ) -> Result<impl Stream<Item = String>, Box<dyn std::error::Error>> {
+    ) -> Result<impl Stream<Item = Result<String, AgentError>>, AgentError> {
         let full_prompt = format!("{}\n\n{}", self.system_prompt, user_prompt);
-        let request = GenerationRequest::new(self.model.clone(), full_prompt);
+        let model = self.model();
+        let request = GenerationRequest::new(model.clone(), full_prompt);
 
-        let stream = self.ollama.generate_stream(request).await?;
-        Ok(stream.map(|res| match res {
+        let stream = self
+            .ollama
+            .generate_stream(request)
+            .await
+            .map_err(|e| Self::map_error_for(&model, e))?;
+        Ok(stream.map(move |res| match res {
             Ok(responses) => {
                 let mut combined = String::new();
                 for resp in responses {
                     combined.push_str(&resp.response);
                 }
-                combined
+                Ok(combined)
             }
-            Err(_) => String::new(),
+            Err(e) => Err(Self::map_error_for(&model, e)),
         }))
     }
+
+    /// 将 ollama-rs 的错误转换为 AgentError
+    fn map_error_for(model: &str, e: OllamaError) -> AgentError {
+        match e {
+            OllamaError::ReqwestError(e) => e.into(),
+            OllamaError::InternalError(e) => {
+                AgentError::from_server_message(model, None, &e.message)
+            }
+            OllamaError::Other(message) => AgentError::from_server_message(model, None, &message),
+            other => AgentError::Server(other.to_string()),
+        }
+    }
+
+    /// 将生成参数转换为 Ollama 的 options，没有设置任何参数时返回 None
+    fn to_model_options(options: &ChatOptions) -> Option<ModelOptions> {
+        if options.temperature.is_none()
+            && options.top_p.is_none()
+            && options.num_ctx.is_none()
+            && options.seed.is_none()
+        {
+            return None;
+        }
+
+        let mut model_options = ModelOptions::default();
+        if let Some(temperature) = options.temperature {
+            model_options = model_options.temperature(temperature);
+        }
+        if let Some(top_p) = options.top_p {
+            model_options = model_options.top_p(top_p);
+        }
+        if let Some(num_ctx) = options.num_ctx {
+            model_options = model_options.num_ctx(num_ctx);
+        }
+        if let Some(seed) = options.seed {
+            model_options = model_options.seed(seed);
+        }
+        Some(model_options)
+    }
+
+    /// 转换为 ollama-rs 的聊天消息
+    fn to_chat_message(turn: ChatTurn) -> ChatMessage {
+        match turn.role {
+            ChatRole::System => ChatMessage::system(turn.content),
+            ChatRole::User => ChatMessage::user(turn.content),
+            ChatRole::Assistant => ChatMessage::assistant(turn.content),
+        }
+    }
+}
+
+impl ChatBackend for OllamaAgent {
+    fn provider(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> String {
+        self.model.read().unwrap().clone()
+    }
+
+    fn set_model(&self, model: &str) {
+        *self.model.write().unwrap() = model.to_string();
+    }
+
+    /// 使用 Ollama 的 chat 接口进行多轮对话
+    fn chat_stream<'a>(
+        &'a self,
+        history: &'a [Message],
+        options: &'a ChatOptions,
+    ) -> BoxFuture<'a, Result<ChatStream, AgentError>> {
+        Box::pin(async move {
+            let system_prompt = options
+                .system_prompt
+                .as_ref()
+                .unwrap_or(&self.system_prompt);
+            let messages = build_chat_turns(system_prompt, history)
+                .into_iter()
+                .map(Self::to_chat_message)
+                .collect();
+            let model = options.model.clone().unwrap_or_else(|| self.model());
+            let mut request = ChatMessageRequest::new(model.clone(), messages);
+            if let Some(model_options) = Self::to_model_options(options) {
+                request = request.options(model_options);
+            }
+
+            let stream = self
+                .ollama
+                .send_chat_messages_stream(request)
+                .await
+                .map_err(|e| Self::map_error_for(&model, e))?;
+            // ollama-rs 在读取响应流失败时只返回 Err(())，只能视为连接中断
+            let stream: ChatStream = Box::pin(stream.map(|res| match res {
+                Ok(response) => Ok(response.message.content),
+                Err(_) => Err(AgentError::ConnectionLost(
+                    "读取 Ollama 响应流失败".to_string(),
+                )),
+            }));
+            Ok(stream)
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<ModelSummary>, AgentError>> {
+        Box::pin(async move {
+            let models = self
+                .ollama
+                .list_local_models()
+                .await
+                .map_err(|e| Self::map_error_for(&self.model(), e))?;
+            Ok(models
+                .into_iter()
+                .map(|m| ModelSummary {
+                    name: m.name,
+                    size: Some(m.size),
+                    modified_at: Some(m.modified_at),
+                })
+                .collect())
+        })
+    }
+
+    fn pull_model<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<PullStream, AgentError>> {
+        Box::pin(async move {
+            let stream = self
+                .ollama
+                .pull_model_stream(name.to_string(), false)
+                .await
+                .map_err(|e| Self::map_error_for(name, e))?;
+            let model = name.to_string();
+            let stream: PullStream = Box::pin(stream.map(move |res| match res {
+                Ok(status) => Ok(PullProgress {
+                    model: model.clone(),
+                    status: status.message,
+                    digest: status.digest,
+                    total: status.total,
+                    completed: status.completed,
+                }),
+                Err(e) => Err(Self::map_error_for(&model, e)),
+            }));
+            Ok(stream)
+        })
+    }
+
+    fn delete_model<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), AgentError>> {
+        Box::pin(async move {
+            self.ollama
+                .delete_model(name.to_string())
+                .await
+                .map_err(|e| Self::map_error_for(name, e))
+        })
+    }
+
+    fn show_model<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<ModelDetails, AgentError>> {
+        Box::pin(async move {
+            let info = self
+                .ollama
+                .show_model_info(name.to_string())
+                .await
+                .map_err(|e| Self::map_error_for(name, e))?;
+            // 上下文长度的键名带有模型架构前缀，例如 qwen2.context_length
+            let context_length = info
+                .model_info
+                .iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .and_then(|(_, value)| value.as_u64());
+            Ok(ModelDetails {
+                name: name.to_string(),
+                parameters: info.parameters,
+                template: info.template,
+                context_length,
+                capabilities: info.capabilities,
+                license: info.license,
+                model_info: info.model_info,
+            })
+        })
+    }
 }
@@ -0,0 +1,304 @@
+use super::{
+    build_chat_turns, AgentError, ChatBackend, ChatOptions, ChatRole, ChatStream, ModelSummary,
+};
+use crate::models::Message;
+use futures::future::BoxFuture;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use tokio_stream::StreamExt;
+
+/// 适用于任意 OpenAI 兼容 `/v1/chat/completions` 接口的后端，
+/// 例如 llama.cpp server、vLLM、LM Studio
+pub struct OpenAiAgent {
+    model: RwLock<String>,
+    base_url: String,
+    api_key: Option<String>,
+    system_prompt: String,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<RequestMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct RequestMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    // 部分服务在流中途出错时会发送带 error 字段的数据块
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: ChunkDelta,
+}
+
+#[derive(Deserialize, Default)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+impl OpenAiAgent {
+    pub fn new(model: &str, host: &str, port: &u16) -> Self {
+        Self {
+            model: RwLock::new(model.to_string()),
+            base_url: format!("{}:{}/v1", host.trim_end_matches('/'), port),
+            api_key: None,
+            system_prompt: "你是一个使用中文作为主要语言的问答助手。".to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_system_prompt(mut self, prompt: &str) -> Self {
+        self.system_prompt = prompt.to_string();
+        self
+    }
+
+    /// 设置 API Key，为空时不发送认证头
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = if api_key.trim().is_empty() {
+            None
+        } else {
+            Some(api_key.to_string())
+        };
+        self
+    }
+
+    fn authorized(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    /// 解析一行 SSE 数据，返回其中的增量文本
+    fn parse_sse_line(model: &str, line: &str) -> Option<Result<String, AgentError>> {
+        let data = line.strip_prefix("data:")?.trim();
+        if data.is_empty() || data == "[DONE]" {
+            return None;
+        }
+        match serde_json::from_str::<ChatCompletionChunk>(data) {
+            Ok(chunk) if chunk.error.is_some() => {
+                Some(Err(AgentError::from_server_message(model, None, data)))
+            }
+            Ok(chunk) => {
+                let content: String = chunk
+                    .choices
+                    .into_iter()
+                    .filter_map(|choice| choice.delta.content)
+                    .collect();
+                Some(Ok(content))
+            }
+            Err(e) => {
+                error!("解析响应块失败: {}, 原始数据: {}", e, data);
+                None
+            }
+        }
+    }
+}
+
+impl ChatBackend for OpenAiAgent {
+    fn provider(&self) -> &'static str {
+        "openai"
+    }
+
+    fn model(&self) -> String {
+        self.model.read().unwrap().clone()
+    }
+
+    fn set_model(&self, model: &str) {
+        *self.model.write().unwrap() = model.to_string();
+    }
+
+    fn chat_stream<'a>(
+        &'a self,
+        history: &'a [Message],
+        options: &'a ChatOptions,
+    ) -> BoxFuture<'a, Result<ChatStream, AgentError>> {
+        Box::pin(async move {
+            let system_prompt = options
+                .system_prompt
+                .as_ref()
+                .unwrap_or(&self.system_prompt);
+            let messages = build_chat_turns(system_prompt, history)
+                .into_iter()
+                .map(|turn| RequestMessage {
+                    role: match turn.role {
+                        ChatRole::System => "system",
+                        ChatRole::User => "user",
+                        ChatRole::Assistant => "assistant",
+                    },
+                    content: turn.content,
+                })
+                .collect();
+            let model = options.model.clone().unwrap_or_else(|| self.model());
+            if options.num_ctx.is_some() {
+                debug!("OpenAI 兼容接口不支持 num_ctx，已忽略");
+            }
+            let request = ChatCompletionRequest {
+                model: &model,
+                messages,
+                stream: true,
+                temperature: options.temperature,
+                top_p: options.top_p,
+                seed: options.seed,
+            };
+
+            let url = format!("{}/chat/completions", self.base_url);
+            debug!("请求 OpenAI 兼容接口: {}", url);
+            let response = self
+                .authorized(self.client.post(&url))
+                .json(&request)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(AgentError::from_server_message(&model, Some(status), &body));
+            }
+
+            let mut bytes = response.bytes_stream();
+            let stream: ChatStream = Box::pin(async_stream::stream! {
+                // 按行缓冲原始字节，避免多字节字符被拆开
+                let mut buffer: Vec<u8> = Vec::new();
+                while let Some(chunk) = bytes.next().await {
+                    let chunk = match chunk {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            error!("读取响应流失败: {}", e);
+                            yield Err(AgentError::ConnectionLost(e.to_string()));
+                            return;
+                        }
+                    };
+                    buffer.extend_from_slice(&chunk);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        let line = String::from_utf8_lossy(&line);
+                        match Self::parse_sse_line(&model, line.trim()) {
+                            Some(Ok(content)) => yield Ok(content),
+                            Some(Err(e)) => {
+                                yield Err(e);
+                                return;
+                            }
+                            None => {}
+                        }
+                    }
+                }
+                if let Some(item) = Self::parse_sse_line(&model, String::from_utf8_lossy(&buffer).trim()) {
+                    yield item;
+                }
+            });
+            Ok(stream)
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<ModelSummary>, AgentError>> {
+        Box::pin(async move {
+            let url = format!("{}/models", self.base_url);
+            let response = self.authorized(self.client.get(&url)).send().await?;
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                return Err(AgentError::from_server_message(
+                    &self.model(),
+                    Some(status),
+                    &body,
+                ));
+            }
+            let list: ModelList = response.json().await?;
+            Ok(list
+                .data
+                .into_iter()
+                .map(|m| ModelSummary {
+                    name: m.id,
+                    size: None,
+                    modified_at: None,
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn dropping_stream_closes_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // 发送一个响应块后保持连接，直到客户端断开
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            assert!(socket.read(&mut buf).await.unwrap() > 0);
+            let event = "data: {\"choices\":[{\"delta\":{\"content\":\"你好\"}}]}\n\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                event.len(),
+                event
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            loop {
+                match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+            }
+        });
+
+        let agent = OpenAiAgent::new("test", "http://127.0.0.1", &port);
+        let history = vec![Message {
+            id: 1,
+            content: "你好".to_string(),
+            sender: "user".to_string(),
+            timestamp: 1,
+            conversation_id: 1,
+            partial: false,
+            parent_id: None,
+        }];
+        let options = ChatOptions::default();
+        let mut stream = agent.chat_stream(&history, &options).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "你好");
+
+        drop(stream);
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("丢弃回复流后连接没有关闭")
+            .unwrap();
+    }
+}
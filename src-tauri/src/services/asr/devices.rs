@@ -0,0 +1,38 @@
+use crate::models::AudioInputDevice;
+use cpal::traits::{DeviceTrait, HostTrait};
+#[cfg(not(feature = "python-asr"))]
+use log::warn;
+
+/// 列出所有可用的麦克风
+pub fn input_devices() -> Result<Vec<AudioInputDevice>, String> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let devices = host
+        .input_devices()
+        .map_err(|e| format!("获取麦克风列表失败: {}", e))?;
+
+    Ok(devices
+        .filter_map(|device| device.name().ok())
+        .map(|name| AudioInputDevice {
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+        })
+        .collect())
+}
+
+/// 按名称查找麦克风，名称为空或找不到时使用系统默认设备
+#[cfg(not(feature = "python-asr"))]
+pub fn find_input_device(name: &str) -> Option<cpal::Device> {
+    let host = cpal::default_host();
+    if !name.is_empty() {
+        let found = host
+            .input_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| n == name)));
+        if found.is_some() {
+            return found;
+        }
+        warn!("找不到麦克风 {}，改用系统默认设备", name);
+    }
+    host.default_input_device()
+}
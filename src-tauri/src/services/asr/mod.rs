@@ -1,2 +1,41 @@
-// pub mod vosk;
+pub mod devices;
+#[cfg(not(feature = "python-asr"))]
+pub mod resample;
+#[cfg(not(feature = "python-asr"))]
+pub mod vad;
+#[cfg(not(feature = "python-asr"))]
+pub mod vosk;
+#[cfg(feature = "python-asr")]
 pub mod vosk_python;
+
+// 默认使用原生的 vosk 实现，启用 python-asr 特性时改用 Python 版本
+#[cfg(not(feature = "python-asr"))]
+pub use vosk::{VoskASR, VoskStream};
+#[cfg(feature = "python-asr")]
+pub use vosk_python::{VoskASR, VoskStream};
+
+use crate::utils::config::{resolve_resource_path, VoiceConfig};
+use log::{error, info};
+use tauri::AppHandle;
+
+/// 未启用自定义语音配置时使用的内置模型
+const DEFAULT_VOSK_MODEL_PATH: &str = "model/vosk-model-small-cn-0.22";
+
+/// 根据语音配置创建 Vosk ASR 实例
+pub fn create_vosk_asr(handle: &AppHandle, voice: &VoiceConfig) -> Result<VoskASR, String> {
+    let model_path = if voice.enabled {
+        voice.model_path.as_str()
+    } else {
+        DEFAULT_VOSK_MODEL_PATH
+    };
+    let model_path = resolve_resource_path(handle, model_path)?;
+    info!("Vosk model path: {:?}", model_path);
+
+    let mut vosk_asr = VoskASR::new(Some(&model_path)).map_err(|e| {
+        error!("VoskASR initialization failed: {}", e);
+        e.to_string()
+    })?;
+    vosk_asr.set_input_device(&voice.input_device);
+    vosk_asr.set_vad_config(voice.vad.clone());
+    Ok(vosk_asr)
+}
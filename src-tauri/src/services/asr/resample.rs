@@ -0,0 +1,71 @@
+/// 流式线性插值重采样，把麦克风的采样率转换为模型需要的采样率
+///
+/// 音频按块送入，块之间保留上一块的最后一个采样点，保证插值连续
+pub struct LinearResampler {
+    /// 每输出一个采样点，在输入中前进的距离
+    step: f64,
+    /// 下一个输出采样点在当前块中的位置，-1 表示上一块的最后一个采样点
+    position: f64,
+    previous: f32,
+}
+
+impl LinearResampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            step: from_rate as f64 / to_rate as f64,
+            position: 0.0,
+            previous: 0.0,
+        }
+    }
+
+    /// 输入 [-1, 1] 的单声道采样，输出目标采样率的 16 位采样
+    pub fn process(&mut self, input: &[f32]) -> Vec<i16> {
+        let Some(&last) = input.last() else {
+            return Vec::new();
+        };
+        let end = (input.len() - 1) as f64;
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+
+        while self.position < end {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            let current = if index < 0.0 {
+                self.previous
+            } else {
+                input[index as usize]
+            };
+            let next = input[(index + 1.0) as usize];
+            let value = current + (next - current) * fraction;
+            output.push((value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+            self.position += self.step;
+        }
+
+        self.position -= input.len() as f64;
+        self.previous = last;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_rate_keeps_samples_across_chunks() {
+        let mut resampler = LinearResampler::new(16000, 16000);
+        let mut output = resampler.process(&[0.0, 0.5, 1.0]);
+        output.extend(resampler.process(&[-0.5, -1.0]));
+        // 每块的最后一个采样点要等到下一块才能输出
+        assert_eq!(output, vec![0, 16383, 32767, -16383]);
+    }
+
+    #[test]
+    fn downsamples_to_target_rate() {
+        let mut resampler = LinearResampler::new(48000, 16000);
+        let output: Vec<i16> = (0..100)
+            .flat_map(|_| resampler.process(&[0.25; 480]))
+            .collect();
+        assert!((output.len() as i64 - 16000).abs() <= 1);
+        assert!(output.iter().all(|&s| s == (0.25 * i16::MAX as f32) as i16));
+    }
+}
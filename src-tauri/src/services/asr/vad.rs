@@ -0,0 +1,214 @@
+use crate::utils::config::VadConfig;
+use std::collections::VecDeque;
+
+// 每帧的时长
+const FRAME_MS: u64 = 20;
+// 噪声底的下限，避免数字静音时任何一点声音都被当成语音
+const MIN_NOISE_FLOOR_DB: f32 = -60.0;
+// 噪声底每帧向当前音量靠近的比例，下降得快一些，以便尽快适应变安静的环境
+const NOISE_RISE: f32 = 0.02;
+const NOISE_FALL: f32 = 0.2;
+// 说话时噪声底仍然非常缓慢地上升，持续不断的噪声十几秒后会被当作环境噪声，
+// 不会一直算作说话；正常说话有停顿，停顿时噪声底会很快降回去
+const NOISE_RISE_SPEAKING: f32 = 0.002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    /// 开始说话
+    SpeechStart,
+    /// 停止说话超过 hangover 时间，一句话结束
+    SpeechEnd,
+}
+
+#[derive(Debug, Default)]
+pub struct VadOutput {
+    /// 应该送入识别器的音频，开始说话时包含之前保留的音频
+    pub audio: Vec<i16>,
+    pub events: Vec<VadEvent>,
+}
+
+/// 基于音量的语音活动检测，噪声底随环境噪声自动调整
+///
+/// 音频按固定长度分帧，音量高出噪声底 `threshold_db` 的帧视为语音
+pub struct VoiceActivityDetector {
+    config: VadConfig,
+    frame_len: usize,
+    frame: Vec<i16>,             // 未凑满一帧的采样
+    noise_floor_db: Option<f32>, // 收到第一帧后初始化
+    speaking: bool,
+    pre_roll: VecDeque<i16>, // 开始说话前的音频
+    pre_roll_len: usize,
+    speech_ms: u64,  // 未开始说话时连续语音的时长
+    silence_ms: u64, // 说话过程中连续静音的时长
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: VadConfig, sample_rate: u32) -> Self {
+        let samples_per_ms = sample_rate as usize / 1000;
+        // 确认开始说话之前的语音帧也要保留下来
+        let pre_roll_len = (config.pre_roll_ms + config.min_speech_ms) as usize * samples_per_ms;
+        Self {
+            config,
+            frame_len: (FRAME_MS as usize * samples_per_ms).max(1),
+            frame: Vec::new(),
+            noise_floor_db: None,
+            speaking: false,
+            pre_roll: VecDeque::with_capacity(pre_roll_len),
+            pre_roll_len,
+            speech_ms: 0,
+            silence_ms: 0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[i16]) -> VadOutput {
+        let mut output = VadOutput::default();
+        for &sample in samples {
+            self.frame.push(sample);
+            if self.frame.len() == self.frame_len {
+                let frame = std::mem::take(&mut self.frame);
+                self.process_frame(&frame, &mut output);
+                self.frame = frame;
+                self.frame.clear();
+            }
+        }
+        output
+    }
+
+    fn process_frame(&mut self, frame: &[i16], output: &mut VadOutput) {
+        let level = level_db(frame);
+        let floor = *self
+            .noise_floor_db
+            .get_or_insert(level.max(MIN_NOISE_FLOOR_DB));
+        let is_speech = level > floor + self.config.threshold_db;
+        if !is_speech {
+            self.update_noise_floor(floor, level);
+        } else if self.speaking {
+            self.noise_floor_db = Some(floor + (level - floor) * NOISE_RISE_SPEAKING);
+        }
+
+        if self.speaking {
+            output.audio.extend_from_slice(frame);
+            if is_speech {
+                self.silence_ms = 0;
+            } else {
+                self.silence_ms += FRAME_MS;
+                if self.silence_ms >= self.config.hangover_ms {
+                    self.speaking = false;
+                    self.silence_ms = 0;
+                    output.events.push(VadEvent::SpeechEnd);
+                }
+            }
+            return;
+        }
+
+        self.pre_roll.extend(frame);
+        let excess = self.pre_roll.len().saturating_sub(self.pre_roll_len);
+        self.pre_roll.drain(..excess);
+
+        if !is_speech {
+            self.speech_ms = 0;
+            return;
+        }
+        self.speech_ms += FRAME_MS;
+        if self.speech_ms >= self.config.min_speech_ms {
+            self.speaking = true;
+            self.speech_ms = 0;
+            output.audio.extend(self.pre_roll.drain(..));
+            output.events.push(VadEvent::SpeechStart);
+        }
+    }
+
+    fn update_noise_floor(&mut self, floor: f32, level: f32) {
+        let rate = if level < floor {
+            NOISE_FALL
+        } else {
+            NOISE_RISE
+        };
+        let floor = floor + (level - floor) * rate;
+        self.noise_floor_db = Some(floor.max(MIN_NOISE_FLOOR_DB));
+    }
+}
+
+// 一帧音频的 RMS 音量，单位为 dBFS
+fn level_db(frame: &[i16]) -> f32 {
+    let sum: f64 = frame.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    let rms = (sum / frame.len() as f64).sqrt() / i16::MAX as f64;
+    20.0 * (rms.max(1e-6) as f32).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    // 指定音量的方波
+    fn tone(amplitude: i16, ms: u64) -> Vec<i16> {
+        (0..ms as usize * RATE as usize / 1000)
+            .map(|i| if i % 2 == 0 { amplitude } else { -amplitude })
+            .collect()
+    }
+
+    fn run(vad: &mut VoiceActivityDetector, audio: &[i16]) -> (usize, Vec<VadEvent>) {
+        let mut samples = 0;
+        let mut events = Vec::new();
+        for chunk in audio.chunks(160) {
+            let output = vad.process(chunk);
+            samples += output.audio.len();
+            events.extend(output.events);
+        }
+        (samples, events)
+    }
+
+    #[test]
+    fn detects_utterance_with_pre_roll_and_hangover() {
+        let config = VadConfig::default();
+        let mut vad = VoiceActivityDetector::new(config.clone(), RATE);
+
+        let (samples, events) = run(&mut vad, &tone(100, 1000));
+        assert_eq!((samples, events), (0, vec![]));
+
+        let (samples, events) = run(&mut vad, &tone(8000, 500));
+        assert_eq!(events, vec![VadEvent::SpeechStart]);
+        // 说话前保留的音频和说话的音频都送入识别器
+        let pre_roll = (config.pre_roll_ms * RATE as u64 / 1000) as usize;
+        assert_eq!(samples, pre_roll + tone(8000, 500).len());
+
+        let (_, events) = run(&mut vad, &tone(100, config.hangover_ms - 100));
+        assert!(events.is_empty());
+        let (_, events) = run(&mut vad, &tone(100, 200));
+        assert_eq!(events, vec![VadEvent::SpeechEnd]);
+    }
+
+    #[test]
+    fn ignores_short_clicks() {
+        let mut vad = VoiceActivityDetector::new(VadConfig::default(), RATE);
+        run(&mut vad, &tone(100, 500));
+        let (samples, events) = run(&mut vad, &tone(8000, 40));
+        assert_eq!((samples, events), (0, vec![]));
+    }
+
+    #[test]
+    fn adapts_to_background_noise() {
+        let mut vad = VoiceActivityDetector::new(VadConfig::default(), RATE);
+        // 持续的嗡嗡声逐渐成为噪声底，不算作说话
+        let (_, events) = run(&mut vad, &tone(1000, 100));
+        assert!(events.is_empty());
+        let (_, events) = run(&mut vad, &tone(2000, 2000));
+        assert!(events.is_empty());
+        // 明显高于环境噪声的声音仍然能被检测到
+        let (_, events) = run(&mut vad, &tone(12000, 300));
+        assert_eq!(events, vec![VadEvent::SpeechStart]);
+    }
+
+    #[test]
+    fn sustained_noise_step_ends_utterance() {
+        let mut vad = VoiceActivityDetector::new(VadConfig::default(), RATE);
+        run(&mut vad, &tone(100, 1000));
+        // 环境噪声突然变大并一直持续，开始时被当作说话，但不会永远算作说话
+        let (_, events) = run(&mut vad, &tone(8000, 20000));
+        assert_eq!(events, vec![VadEvent::SpeechStart, VadEvent::SpeechEnd]);
+        let (samples, events) = run(&mut vad, &tone(8000, 2000));
+        assert_eq!((samples, events), (0, vec![]));
+    }
+}
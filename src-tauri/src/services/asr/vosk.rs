@@ -1,347 +1,569 @@
-use anyhow::{Context, Result};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use log::{error, info, warn}; // 移除未使用的debug导入
-use num_traits::ToPrimitive;
-use tokio_stream::Stream;
+use super::devices::find_input_device;
+use super::resample::LinearResampler;
+use super::vad::{VadEvent, VoiceActivityDetector};
+use crate::models::{Transcription, WordTiming};
+use crate::utils::config::VadConfig;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use futures::Stream;
+use log::{debug, error, info, warn};
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio::time::Duration;
-use vosk::{Model, Recognizer};
-use std::pin::Pin;
-use std::task::{Context as TaskContext, Poll};
-use std::fmt;
-use futures;
-
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc as tokio_mpsc, oneshot};
+use vosk::{DecodingState, Model, Recognizer};
+
+// 读不到模型配置时使用的采样率，Vosk 的模型基本都是 16kHz
+const DEFAULT_MODEL_SAMPLE_RATE: u32 = 16000;
+// 等待音频数据的最长时间，保证能及时响应停止和超时
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// 转写文件时每次送入识别器的音频长度
+const FILE_CHUNK_MS: u32 = 200;
+// 录音时回调音量的间隔
+const LEVEL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 基于 vosk 和 cpal 的语音识别，不依赖 Python
 pub struct VoskASR {
-    model: Model,
-    is_recording: Arc<AtomicBool>,
+    model_path: String,
+    model: Option<Arc<Model>>, // 第一次录音时才加载
+    sample_rate: u32,
+    input_device: String,             // 为空时使用系统默认麦克风
+    vad: VadConfig,                   // 检测开始说话和一句话结束
+    running: Option<Arc<AtomicBool>>, // 当前识别会话的运行标志
 }
 
+impl VoskASR {
+    pub fn new(model_path: Option<&str>) -> Result<Self, String> {
+        debug!(
+            "Creating VoskASR with path {}",
+            model_path.unwrap_or("None")
+        );
+
+        // 只保存模型路径，不立即加载模型
+        let model_path = model_path.unwrap_or("model").to_string();
+        Ok(Self {
+            sample_rate: model_sample_rate(&model_path),
+            model_path,
+            model: None,
+            input_device: String::new(),
+            vad: VadConfig::default(),
+            running: None,
+        })
+    }
 
-#[derive(Debug)]
-struct VoskError(String);
+    /// 设置语音检测参数，下一次录音时生效
+    pub fn set_vad_config(&mut self, vad: VadConfig) {
+        self.vad = vad;
+    }
 
-// 实现 Display 和 Error
-impl fmt::Display for VoskError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+    /// 设置录音使用的麦克风，下一次录音时生效
+    pub fn set_input_device(&mut self, name: &str) {
+        self.input_device = name.to_string();
     }
-}
 
-impl std::error::Error for VoskError {}
+    // 加载模型（但不启动麦克风）
+    fn ensure_initialized(&mut self) -> Result<Arc<Model>, String> {
+        if let Some(model) = &self.model {
+            return Ok(model.clone());
+        }
 
-// VoskError 已经可以通过标准库自动转换为 Box<dyn std::error::Error + Send + Sync>
-// 因为它已经实现了 std::error::Error
+        info!("Loading Vosk model from path: {}", self.model_path);
+        let model = Model::new(self.model_path.as_str())
+            .map(Arc::new)
+            .ok_or_else(|| format!("加载 Vosk 模型失败: {}", self.model_path))?;
+        self.model = Some(model.clone());
+        Ok(model)
+    }
 
-impl VoskASR {
-    pub fn new(model_path: Option<&str>) -> Result<Self> {
-        // 使用提供的路径或默认路径
-        let path = model_path.unwrap_or("model");
-        info!("Loading Vosk model from path: {}", path);
+    // 显式停止录音，采集线程会输出最终结果后退出
+    pub fn stop_recording(&mut self) -> Result<(), String> {
+        if let Some(running) = self.running.take() {
+            running.store(false, Ordering::SeqCst);
+            debug!("Stopped audio stream");
+        }
+        Ok(())
+    }
 
-        let model = Model::new(path)
-            .with_context(|| format!("Failed to load Vosk model from path: {}", path))?;
+    /// 打开麦克风开始识别，`timeout_secs` 秒后自动结束
+    ///
+    /// 检测到开始说话后才把音频送入识别器，说完一句话后自动结束。
+    /// 流中的每一项都是到目前为止的完整识别文本，另有 `[speech started]`、
+    /// `[silence detected]` 和 `[timeout reached]` 三个标记。
+    /// 录音期间定时以 0 到 1 的 RMS 音量调用 `on_level`
+    pub async fn listen_and_transcribe<F>(
+        &mut self,
+        timeout_secs: Option<u64>,
+        on_level: F,
+    ) -> Result<VoskStream, String>
+    where
+        F: Fn(f32) + Send + 'static,
+    {
+        self.stop_recording()?;
+        let model = self.ensure_initialized()?;
 
-        Ok(Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (sender, receiver) = tokio_mpsc::channel(32);
+        let session = CaptureSession {
             model,
-            is_recording: Arc::new(AtomicBool::new(false)),
+            sample_rate: self.sample_rate,
+            input_device: self.input_device.clone(),
+            vad: self.vad.clone(),
+            running: running.clone(),
+            timeout: timeout_secs.map(Duration::from_secs),
+            sender,
+            on_level: Box::new(on_level),
+        };
+
+        // cpal 的音频流不能跨线程移动，采集和识别都放在专用线程中
+        thread::Builder::new()
+            .name("vosk-capture".to_string())
+            .spawn(move || session.run(ready_tx))
+            .map_err(|e| format!("启动录音线程失败: {}", e))?;
+
+        ready_rx
+            .await
+            .map_err(|_| "录音线程意外退出".to_string())??;
+        debug!("语音识别启动成功，超时设置: {:?}秒", timeout_secs);
+
+        self.running = Some(running.clone());
+        Ok(VoskStream { receiver, running })
+    }
+
+    /// 转写音频文件（WAV/FLAC/MP3/OGG），返回文本和每个词的时间
+    ///
+    /// 识别出的文本有变化时调用 `on_partial`，参数为到目前为止的完整文本
+    pub async fn transcribe_file<F>(
+        &mut self,
+        path: &Path,
+        on_partial: F,
+    ) -> Result<Transcription, String>
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        let model = self.ensure_initialized()?;
+        let sample_rate = self.sample_rate;
+        let path = path.to_path_buf();
+
+        // 解码和识别都很耗时，放到阻塞线程池中执行
+        tokio::task::spawn_blocking(move || {
+            transcribe_audio_file(&model, sample_rate, &path, on_partial)
         })
+        .await
+        .map_err(|e| format!("转写任务异常退出: {}", e))?
     }
+}
 
-    /// 检查是否正在录音
-    pub fn is_recording(&self) -> bool {
-        self.is_recording.load(Ordering::SeqCst)
+impl Drop for VoskASR {
+    fn drop(&mut self) {
+        // 确保在实例被销毁时停止任何录音
+        self.stop_recording().ok();
     }
+}
+
+// 从模型的 conf/mfcc.conf 中读取模型训练时的采样率
+fn model_sample_rate(model_path: &str) -> u32 {
+    let conf = Path::new(model_path).join("conf").join("mfcc.conf");
+    std::fs::read_to_string(conf)
+        .ok()
+        .and_then(|conf| {
+            conf.lines().find_map(|line| {
+                let rate = line.trim().strip_prefix("--sample-frequency=")?;
+                rate.trim().parse::<f32>().ok()
+            })
+        })
+        .map(|rate| rate as u32)
+        .unwrap_or(DEFAULT_MODEL_SAMPLE_RATE)
+}
 
-    /// 手动停止正在进行的录音
-    pub fn stop_recording(&self) {
-        if self.is_recording() {
-            info!("Manually stopping voice recording");
-            self.is_recording.store(false, Ordering::SeqCst);
+fn transcribe_audio_file(
+    model: &Model,
+    sample_rate: u32,
+    path: &Path,
+    on_partial: impl Fn(&str),
+) -> Result<Transcription, String> {
+    let file = File::open(path).map_err(|e| format!("打开音频文件失败: {}", e))?;
+    let mut decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| format!("无法解码音频文件: {}", e))?;
+    let channels = decoder.channels().max(1) as usize;
+    let file_rate = decoder.sample_rate();
+    info!(
+        "转写音频文件: {}，{}Hz，{} 声道",
+        path.display(),
+        file_rate,
+        channels
+    );
+
+    let mut recognizer = Recognizer::new(model, sample_rate as f32)
+        .ok_or_else(|| "创建 Vosk 识别器失败".to_string())?;
+    recognizer.set_words(true);
+    let mut transcript = Transcript::new(recognizer);
+    let mut resampler = LinearResampler::new(file_rate, sample_rate);
+
+    let chunk_len = (file_rate * FILE_CHUNK_MS / 1000).max(1) as usize * channels;
+    let mut chunk: Vec<i16> = Vec::with_capacity(chunk_len);
+    loop {
+        chunk.clear();
+        chunk.extend(decoder.by_ref().take(chunk_len));
+        if chunk.is_empty() {
+            break;
+        }
+        let samples = resampler.process(&to_mono(&chunk, channels));
+        if let Some(text) = transcript.accept(&samples)? {
+            on_partial(&text);
         }
     }
+    if let Some(text) = transcript.finish() {
+        on_partial(&text);
+    }
 
-    /// 创建音频录音流
-    fn create_stream<T>(
-        &self,
-        device: &cpal::Device,
-        config: &cpal::StreamConfig,
-        tx: mpsc::Sender<Vec<i16>>, // 修改通道类型为样本类型（如 i16）
-    ) -> Result<cpal::Stream>
-    where
-        T: cpal::Sample + cpal::SizedSample + ToPrimitive + Send + 'static,
-    {
-        let is_recording = self.is_recording.clone();
+    let transcription = transcript.into_transcription();
+    info!("音频文件转写完成，共 {} 个词", transcription.words.len());
+    Ok(transcription)
+}
 
-        let err_fn = |err| error!("Recording error: {}", err);
+/// 一次识别会话，在录音线程中运行
+struct CaptureSession {
+    model: Arc<Model>,
+    sample_rate: u32,
+    input_device: String,
+    vad: VadConfig,
+    running: Arc<AtomicBool>,
+    timeout: Option<Duration>,
+    sender: tokio_mpsc::Sender<Result<String, String>>,
+    on_level: Box<dyn Fn(f32) + Send>,
+}
 
-        let stream = device.build_input_stream(
-            config,
-            move |data: &[T], _: &_| {
-                // 检查是否应该继续录音
-                if !is_recording.load(Ordering::SeqCst) {
-                    return;
+impl CaptureSession {
+    fn run(self, ready: oneshot::Sender<Result<(), String>>) {
+        let (audio_tx, audio_rx) = mpsc::channel();
+        let (stream, device_rate, recognizer) = match self.start(audio_tx) {
+            Ok(started) => started,
+            Err(e) => {
+                error!("启动语音识别失败: {}", e);
+                let _ = ready.send(Err(e));
+                return;
+            }
+        };
+        let _ = ready.send(Ok(()));
+
+        let mut resampler = LinearResampler::new(device_rate, self.sample_rate);
+        let mut transcript = Transcript::new(recognizer);
+        let start = Instant::now();
+        let mut vad = VoiceActivityDetector::new(self.vad.clone(), self.sample_rate);
+        let mut timed_out = false;
+        let mut utterance_ended = false;
+        let mut level = LevelMeter::default();
+
+        while self.running.load(Ordering::SeqCst) {
+            if self
+                .timeout
+                .is_some_and(|timeout| start.elapsed() >= timeout)
+            {
+                info!("达到超时时间 {:?}", self.timeout);
+                timed_out = true;
+                break;
+            }
+
+            let chunk: Vec<f32> = match audio_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(chunk) => chunk,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("麦克风音频流已关闭");
+                    break;
+                }
+            };
+            if let Some(rms) = level.push(&chunk) {
+                (self.on_level)(rms);
+            }
+            // 开始说话之前的音频不送入识别器
+            let output = vad.process(&resampler.process(&chunk));
+            if output.events.contains(&VadEvent::SpeechStart) {
+                debug!("检测到开始说话");
+                if !self.send(Ok("[speech started]".to_string())) {
+                    break;
                 }
+            }
+            if output.audio.is_empty() {
+                continue;
+            }
 
-                // 将音频数据转换为i16格式
-                let samples: Vec<i16> = data
-                    .iter()
-                    .filter_map(|&sample| {
-                        let value = sample.to_f32()?;
-                        Some((value * 32767.0) as i16)
-                    })
-                    .collect();
-
-                // 只有当有数据且通道未满时才发送
-                if !samples.is_empty() {
-                    if tx.try_send(samples).is_err() {
-                        warn!("Audio buffer full, sample dropped");
+            match transcript.accept(&output.audio) {
+                Ok(Some(text)) => {
+                    if !self.send(Ok(text)) {
+                        debug!("接收方已关闭，停止识别");
+                        break;
                     }
                 }
-            },
-            err_fn,
-            None, // 延迟
-        )?;
+                Ok(None) => {}
+                Err(e) => {
+                    error!("识别过程中出错: {}", e);
+                    self.send(Err(e));
+                    self.running.store(false, Ordering::SeqCst);
+                    return;
+                }
+            }
+
+            if output.events.contains(&VadEvent::SpeechEnd) {
+                info!("检测到一句话结束，停止录音");
+                utterance_ended = true;
+                break;
+            }
+        }
+
+        // 先关闭麦克风，再取出最后一句的结果
+        drop(stream);
+        if let Some(text) = transcript.finish() {
+            self.send(Ok(text));
+        }
+        if utterance_ended {
+            self.send(Ok("[silence detected]".to_string()));
+        }
+        if timed_out {
+            self.send(Ok("[timeout reached]".to_string()));
+        }
+        self.running.store(false, Ordering::SeqCst);
+        debug!("识别线程退出");
+    }
 
-        Ok(stream)
+    // 打开麦克风并创建识别器，返回音频流、设备采样率和识别器
+    fn start(
+        &self,
+        audio_tx: mpsc::Sender<Vec<f32>>,
+    ) -> Result<(cpal::Stream, u32, Recognizer), String> {
+        let (stream, device_rate) = open_input(&self.input_device, audio_tx)?;
+        let recognizer = Recognizer::new(&self.model, self.sample_rate as f32)
+            .ok_or_else(|| "创建 Vosk 识别器失败".to_string())?;
+        stream
+            .play()
+            .map_err(|e| format!("启动麦克风失败: {}", e))?;
+        info!(
+            "音频流启动成功，设备采样率 {}Hz，模型采样率 {}Hz",
+            device_rate, self.sample_rate
+        );
+        Ok((stream, device_rate, recognizer))
     }
 
-    // // 提供listen_and_transcribe方法，无需确保与main.rs匹配
-    // pub async fn listen_and_transcribe(
-    //     &mut self,
-    //     max_duration_secs: Option<f32>,
-    // ) -> Result<
-    //     impl tokio_stream::Stream<Item = Result<String, Box<dyn std::error::Error + Send + Sync>>> + use<'_>,
-    //     Box<dyn std::error::Error + Send + Sync>,
-    // > {
-    //     // 验证录音状态
-    //     if self.is_recording() {
-    //         return Err(Box::new(VoskError("Recording session already in progress".into())));
-    //     }
-
-    //     // 设置录音状态原子标志
-    //     self.is_recording.store(true, Ordering::SeqCst);
-
-    //     // 音频设备初始化
-    //     let host = cpal::default_host();
-    //     let device = host
-    //         .default_input_device()
-    //         .ok_or_else(|| Box::new(VoskError("No default input device".into())) as Box<dyn std::error::Error + Send + Sync>)?;
-    //     let config = device.default_input_config()?;
-    //     let sample_rate = config.sample_rate().0 as f32;
-
-    //     // 创建识别器
-    //     let recognizer = Arc::new(tokio::sync::Mutex::new(Recognizer::new(
-    //         &self.model,
-    //         sample_rate,
-    //     )));
-
-    //     // 创建音频数据通道
-    //     let (audio_tx, audio_rx) = mpsc::channel::<Vec<i16>>(32);
-
-    //     // 启动音频流
-    //     let stream = self.create_stream::<i16>(&device, &config.config(), audio_tx)?;
-    //     stream.play()?;
-
-    //     // 创建结果流
-    //     Ok(stream! {
-    //         let mut audio_rx = audio_rx;
-    //         let is_recording_flag = self.is_recording.clone();
-    //         let _guard = scopeguard::guard(is_recording_flag, |flag| {
-    //             flag.store(false, Ordering::SeqCst);
-    //         });
-
-    //         let start = tokio::time::Instant::now();
-    //         let max_duration = max_duration_secs.map(|s| Duration::from_secs_f32(s));
-    //         let mut silence_counter = 0;
-    //         const SILENCE_THRESHOLD: usize = 30;
-    //         const SOUND_THRESHOLD: i16 = 500;
-
-    //         loop {
-    //             // 计算剩余时间
-    //             let remaining = match max_duration {
-    //                 Some(dur) => {
-    //                     let elapsed = start.elapsed();
-    //                     if elapsed >= dur {
-    //                         // 已超时，退出循环
-    //                         yield Ok("[timeout reached]".to_string());
-    //                         break;
-    //                     }
-    //                     Some(dur - elapsed)
-    //                 }
-    //                 None => None,
-    //             };
-
-    //             // 接收数据（带超时）
-    //             let data = match remaining {
-    //                 Some(rem) => match timeout(rem, audio_rx.recv()).await {
-    //                     Ok(Some(d)) => d,
-    //                     Ok(None) => break, // 通道关闭
-    //                     Err(_) => {
-    //                         yield Ok("[timeout reached]".to_string());
-    //                         break;
-    //                     }
-    //                 },
-    //                 None => match audio_rx.recv().await {
-    //                     Some(d) => d,
-    //                     None => break,
-    //                 },
-    //             };
-
-    //             // 静音检测
-    //             let has_sound = data.iter().any(|&s| s.abs() > SOUND_THRESHOLD);
-    //             silence_counter = if has_sound { 0 } else { silence_counter + 1 };
-
-    //             if silence_counter > SILENCE_THRESHOLD {
-    //                 yield Ok("[silence detected]".to_string());
-    //                 break;
-    //             }
-
-    //             // 语音识别处理
-    //             let mut recognizer_lock = recognizer.lock().await;
-    //             let rec = recognizer_lock.as_mut().unwrap();
-    //             if let Err(e) = rec.accept_waveform(&data) {
-    //                 yield Err(VoskError(format!("Recognition error: {}", e)).into());
-    //                 continue;
-    //             }
-
-    //             // 发送部分结果
-    //             let partial = rec.partial_result().partial;
-    //             if !partial.is_empty() {
-    //                 yield Ok(partial.to_string());
-    //             }
-    //             drop(recognizer_lock);
-    //         };
-
-    //         // 发送最终结果
-    //         let mut recognizer_lock = recognizer.lock().await;
-    //         let rec = recognizer_lock.as_mut().unwrap();
-    //         let final_result = rec.final_result().single().unwrap().text;
-    //         if !final_result.is_empty() {
-    //             yield Ok(final_result.to_string());
-    //         }
-    //         drop(recognizer_lock);
-    //     })
-    // }
-
-    pub async fn listen_and_transcribe(
-        &mut self,
-        max_duration_secs: Option<f32>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, Box<dyn std::error::Error + Send + Sync>>> + Send>>> {
-        // 初始化音频设备
-        let host = cpal::default_host();
-        let device = host.default_input_device()
-            .ok_or_else(|| Box::new(VoskError("No default input device".into())))?;
-        let config = device.default_input_config()?;
-        let sample_rate = config.sample_rate().0 as f32;
-
-        // 创建识别器
-        let recognizer = Arc::new(tokio::sync::Mutex::new(
-            Recognizer::new(&self.model, sample_rate)
-                .ok_or_else(|| VoskError("Failed to create recognizer".into()))?
-        ));
-
-        // 创建音频通道
-        let (audio_tx, audio_rx) = mpsc::channel(32);
-
-        // 创建音频流
-        let stream = self.create_stream::<i16>(&device, &config.config(), audio_tx)?;
-        stream.play()?;
-
-        // 构建 VoskStream
-        let vosk_stream = VoskStream {
-            is_recording: self.is_recording.clone(),
-            audio_rx,
-            recognizer,
-            max_duration: max_duration_secs.map(|s| Duration::from_secs_f32(s)),
-            start_time: Some(tokio::time::Instant::now()),
-            silence_counter: 0,
-        };
+    // 接收方已关闭时返回 false
+    fn send(&self, item: Result<String, String>) -> bool {
+        self.sender.blocking_send(item).is_ok()
+    }
+}
+
+// 打开麦克风，音频转为单声道 f32 后送入通道
+fn open_input(
+    device_name: &str,
+    audio_tx: mpsc::Sender<Vec<f32>>,
+) -> Result<(cpal::Stream, u32), String> {
+    let device = find_input_device(device_name).ok_or_else(|| "没有可用的麦克风".to_string())?;
+    let supported = device
+        .default_input_config()
+        .map_err(|e| format!("读取麦克风配置失败: {}", e))?;
+    let config = supported.config();
+    debug!(
+        "麦克风: {}，{:?}",
+        device.name().unwrap_or_default(),
+        config
+    );
+
+    let stream = match supported.sample_format() {
+        SampleFormat::I16 => build_input_stream::<i16>(&device, &config, audio_tx),
+        SampleFormat::U16 => build_input_stream::<u16>(&device, &config, audio_tx),
+        SampleFormat::I32 => build_input_stream::<i32>(&device, &config, audio_tx),
+        SampleFormat::F32 => build_input_stream::<f32>(&device, &config, audio_tx),
+        format => Err(format!("不支持的采样格式: {:?}", format)),
+    }?;
+    Ok((stream, config.sample_rate.0))
+}
+
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    audio_tx: mpsc::Sender<Vec<f32>>,
+) -> Result<cpal::Stream, String>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let _ = audio_tx.send(to_mono(data, channels));
+            },
+            |e| error!("麦克风采集出错: {}", e),
+            None,
+        )
+        .map_err(|e| format!("打开麦克风失败: {}", e))
+}
 
-        Ok(Box::pin(vosk_stream))
+// 多声道取平均值混成单声道，采样转为 [-1, 1] 的 f32
+fn to_mono<T>(data: &[T], channels: usize) -> Vec<f32>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    data.chunks(channels)
+        .map(|frame| frame.iter().map(|&s| s.to_sample::<f32>()).sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// 按固定间隔计算音频的 RMS 音量
+struct LevelMeter {
+    sum_squares: f64,
+    count: usize,
+    last_report: Instant,
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self {
+            sum_squares: 0.0,
+            count: 0,
+            last_report: Instant::now(),
+        }
     }
 }
 
-impl Drop for VoskASR {
-    fn drop(&mut self) {
-        // 确保在实例被销毁时停止任何录音
-        self.stop_recording();
+impl LevelMeter {
+    // 累计一段音频，到达间隔时返回这段时间的音量
+    fn push(&mut self, samples: &[f32]) -> Option<f32> {
+        self.sum_squares += samples
+            .iter()
+            .map(|&s| (s as f64) * (s as f64))
+            .sum::<f64>();
+        self.count += samples.len();
+        if self.count == 0 || self.last_report.elapsed() < LEVEL_INTERVAL {
+            return None;
+        }
+        let rms = (self.sum_squares / self.count as f64).sqrt() as f32;
+        *self = Self::default();
+        Some(rms.min(1.0))
     }
 }
 
-pub struct VoskStream {
-    is_recording: Arc<AtomicBool>,
-    audio_rx: mpsc::Receiver<Vec<i16>>,
-    recognizer: Arc<tokio::sync::Mutex<Recognizer>>,
-    max_duration: Option<Duration>,
-    start_time: Option<tokio::time::Instant>,
-    silence_counter: usize,
+/// 把 Vosk 按句给出的结果拼接成完整文本
+struct Transcript {
+    recognizer: Recognizer,
+    sentences: Vec<String>, // 已确定的句子
+    partial: String,        // 正在识别的句子
+    words: Vec<WordTiming>, // 识别器开启 set_words 时才有
+    last_sent: String,
 }
 
-impl Stream for VoskStream {
-    type Item = Result<String, Box<dyn std::error::Error + Send + Sync>>;
-
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
-        loop {
-            // 处理超时逻辑
-            if let Some(max_duration) = self.max_duration {
-                let elapsed = self.start_time.unwrap().elapsed();
-                if elapsed >= max_duration {
-                    return Poll::Ready(Some(Ok("[timeout reached]".to_string())));
-                }
-            }
+impl Transcript {
+    fn new(recognizer: Recognizer) -> Self {
+        Self {
+            recognizer,
+            sentences: Vec::new(),
+            partial: String::new(),
+            words: Vec::new(),
+            last_sent: String::new(),
+        }
+    }
 
-            // 接收音频数据
-            match self.audio_rx.poll_recv(cx) {
-                Poll::Ready(Some(data)) => {
-                    // 静音检测逻辑
-                    const SOUND_THRESHOLD: i16 = 500;
-                    let has_sound = data.iter().any(|&s| s.abs() > SOUND_THRESHOLD);
-                    self.silence_counter = if has_sound { 0 } else { self.silence_counter + 1 };
+    // 识别一段音频，完整文本有变化时返回新的文本
+    fn accept(&mut self, samples: &[i16]) -> Result<Option<String>, String> {
+        let state = self
+            .recognizer
+            .accept_waveform(samples)
+            .map_err(|e| format!("识别音频失败: {}", e))?;
+        match state {
+            DecodingState::Running => {
+                self.partial = self.recognizer.partial_result().partial.trim().to_string();
+            }
+            // 检测到句子结束，识别器会从下一句重新开始
+            DecodingState::Finalized => {
+                let sentence = self.sentence_result(false);
+                self.push_sentence(sentence);
+            }
+            DecodingState::Failed => return Err("Vosk 解码失败".to_string()),
+        }
+        Ok(self.changed())
+    }
 
-                    if self.silence_counter > 30 {
-                        return Poll::Ready(Some(Ok("[silence detected]".to_string())));
-                    }
+    // 结束识别，取出最后一句
+    fn finish(&mut self) -> Option<String> {
+        let sentence = self.sentence_result(true);
+        self.push_sentence(sentence);
+        self.changed()
+    }
 
-                    // 语音识别处理
-                    let recognizer = self.recognizer.clone();
-                    // Use try_lock instead of blocking
-                    let mut recognizer = match recognizer.try_lock() {
-                        Ok(guard) => guard,
-                        Err(e) => return Poll::Pending, // Return pending if lock is unavailable
-                    };
+    fn sentence_result(&mut self, last: bool) -> String {
+        let result = if last {
+            self.recognizer.final_result()
+        } else {
+            self.recognizer.result()
+        };
+        let Some(result) = result.single() else {
+            return String::new();
+        };
+        self.words
+            .extend(result.result.iter().map(|word| WordTiming {
+                word: word.word.to_string(),
+                start: word.start,
+                end: word.end,
+                confidence: word.conf,
+            }));
+        result.text.trim().to_string()
+    }
 
-                    if let Err(e) = recognizer.accept_waveform(&data) {
-                        return Poll::Ready(Some(Err(Box::new(VoskError(format!("Recognition error: {}", e))))));
-                    }
+    fn push_sentence(&mut self, sentence: String) {
+        self.partial.clear();
+        if !sentence.is_empty() {
+            self.sentences.push(sentence);
+        }
+    }
 
-                    // 返回部分结果
-                    let partial = recognizer.partial_result().partial;
-                    if !partial.is_empty() {
-                        return Poll::Ready(Some(Ok(partial.to_string())));
-                    }
+    fn into_transcription(self) -> Transcription {
+        Transcription {
+            text: self.sentences.join(" "),
+            words: self.words,
+        }
+    }
 
-                    // // 处理最终结果
-                    // let recognizer = self.recognizer.clone();
-                    // let guard_result = futures::executor::block_on(recognizer.lock());
-                    // let mut recognizer = match guard_result {
-                    //     Ok(guard) => guard,
-                    //     Err(e) => return Poll::Ready(Some(Err(Box::new(e)))),
-                    // };
-
-                    // let final_result = recognizer.final_result().single().unwrap().text;
-                    // if !final_result.is_empty() {
-                    //     return Poll::Ready(Some(Ok(final_result.to_string())));
-                    // }
-                    return Poll::Ready(None);
-                }
-                Poll::Pending => return Poll::Pending,
-                Poll::Ready(None) => {
-                    // 通道关闭，返回 None
-                    return Poll::Ready(None);
-                }
+    fn changed(&mut self) -> Option<String> {
+        let mut text = self.sentences.join(" ");
+        if !self.partial.is_empty() {
+            if !text.is_empty() {
+                text.push(' ');
             }
+            text.push_str(&self.partial);
+        }
+        if text.is_empty() || text == self.last_sent {
+            return None;
         }
+        self.last_sent = text.clone();
+        Some(text)
     }
-}
\ No newline at end of file
+}
+
+/// 识别结果流，释放时结束识别
+pub struct VoskStream {
+    receiver: tokio_mpsc::Receiver<Result<String, String>>,
+    running: Arc<AtomicBool>,
+}
+
+impl Drop for VoskStream {
+    fn drop(&mut self) {
+        debug!("VoskStream被释放");
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Stream for VoskStream {
+    type Item = Result<String, String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -1,7 +1,10 @@
+use crate::models::Transcription;
+use crate::utils::config::VadConfig;
 use futures::Stream;
 use log::{debug, error, info};
 use pyo3::prelude::*;
 use pyo3::types::PyList;
+use std::path::Path;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -10,7 +13,8 @@ use tokio::sync::mpsc as tokio_mpsc;
 #[derive(Debug)]
 pub struct VoskASR {
     model_path: Option<String>,
-    instance: Option<Py<PyAny>>, // 只有在需要时才初始化
+    input_device: Option<String>, // 为空时使用默认麦克风
+    instance: Option<Py<PyAny>>,  // 只有在需要时才初始化
 }
 
 #[allow(dead_code)]
@@ -26,10 +30,20 @@ impl VoskASR {
 
         Ok(Self {
             model_path,
+            input_device: None,
             instance: None,
         })
     }
 
+    // Python 版本使用自己的静音检测
+    pub fn set_vad_config(&mut self, _vad: VadConfig) {}
+
+    // 设置录音使用的麦克风，下一次初始化时生效
+    pub fn set_input_device(&mut self, name: &str) {
+        self.input_device = Some(name.to_string()).filter(|name| !name.is_empty());
+        self.instance = None;
+    }
+
     // 初始化模型和Python实例（但不启动麦克风）
     fn ensure_initialized(&mut self) -> PyResult<()> {
         if self.instance.is_some() {
@@ -58,10 +72,9 @@ impl VoskASR {
             debug!("VoskASR class: {:?}", class);
 
             // 创建实例
-            let instance: Py<PyAny> = match &self.model_path {
-                Some(p) => class.call1((p,))?.into_py(py),
-                None => class.call0()?.into_py(py),
-            };
+            let instance: Py<PyAny> = class
+                .call1((self.model_path.as_deref(), self.input_device.as_deref()))?
+                .into_py(py);
 
             debug!("VoskASR instance created: {:?}", instance);
             self.instance = Some(instance);
@@ -105,8 +118,16 @@ impl VoskASR {
         }
     }
 
+    // Python 版本不回调音量
     #[allow(deprecated)]
-    pub async fn listen_and_transcribe(&mut self, timeout_ms: Option<u64>) -> PyResult<VoskStream> {
+    pub async fn listen_and_transcribe<F>(
+        &mut self,
+        timeout_secs: Option<u64>,
+        _on_level: F,
+    ) -> PyResult<VoskStream>
+    where
+        F: Fn(f32) + Send + 'static,
+    {
         self.ensure_initialized()?;
         self.start_recording()?; // 确保在开始转录前启动录音
 
@@ -116,14 +137,14 @@ impl VoskASR {
             let instance = self.instance.as_ref().unwrap().clone_ref(py);
 
             // Start recognition with the timeout parameter and set end_on_silence=false
-            let timeout_py = match timeout_ms {
+            let timeout_py = match timeout_secs {
                 Some(ms) => ms.into_py(py),
                 None => py.None(),
             };
 
             // 传递第二个参数false，表示检测到静默时不要自动结束录音
             match instance.call_method1(py, "start_recognition", (timeout_py, false)) {
-                Ok(_) => debug!("语音识别启动成功，超时设置: {:?}ms", timeout_ms),
+                Ok(_) => debug!("语音识别启动成功，超时设置: {:?}秒", timeout_secs),
                 Err(e) => error!("启动语音识别失败: {:?}", e),
             }
 
@@ -186,6 +207,21 @@ impl VoskASR {
             is_active: true,
         })
     }
+
+    // Python 版本只支持麦克风输入
+    pub async fn transcribe_file<F>(
+        &mut self,
+        path: &Path,
+        _on_partial: F,
+    ) -> Result<Transcription, String>
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        Err(format!(
+            "Python 语音识别后端不支持转写音频文件: {}",
+            path.display()
+        ))
+    }
 }
 
 // 创建一个新的结构体以支持异步流
@@ -290,7 +326,7 @@ mod tests {
     #[tokio::test]
     async fn test_vosk_stream() {
         let mut vosk_asr = VoskASR::new(Some("C:/Users/18511/Documents/AppCode/Rust/chat_box/src-tauri/model/vosk-model-small-cn-0.22")).unwrap();
-        let mut stream = vosk_asr.listen_and_transcribe(None).await.unwrap();
+        let mut stream = vosk_asr.listen_and_transcribe(None, |_| {}).await.unwrap();
 
         while let Some(result) = stream.next().await {
             match result {
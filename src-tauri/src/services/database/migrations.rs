@@ -0,0 +1,465 @@
+use chrono::Local;
+use log::{info, warn};
+use rusqlite::{Connection, Transaction};
+use std::fmt;
+use std::path::Path;
+
+/// 数据库结构迁移步骤，版本号记录在 `PRAGMA user_version` 中
+struct Migration {
+    version: u32,
+    description: &'static str,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// 按版本顺序排列，只能在末尾追加，已发布的步骤不能修改
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "创建对话和消息表",
+        up: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        description: "消息增加 partial 字段",
+        up: add_message_partial,
+    },
+    Migration {
+        version: 3,
+        description: "创建对话模型设置表",
+        up: create_conversation_settings,
+    },
+    Migration {
+        version: 4,
+        description: "创建消息全文索引",
+        up: create_search_index,
+    },
+    Migration {
+        version: 5,
+        description: "消息增加 parent_id 以支持分支",
+        up: add_message_branches,
+    },
+    Migration {
+        version: 6,
+        description: "对话增加置顶、归档和标签",
+        up: add_conversation_organization,
+    },
+    Migration {
+        version: 7,
+        description: "对话和消息ID改为自增，删除后不再复用",
+        up: use_autoincrement_ids,
+    },
+];
+
+/// 当前程序支持的数据库结构版本
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// 打开或升级数据库时的错误
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    /// 数据库由更新版本的程序创建
+    NewerThanApp {
+        found: u32,
+        supported: u32,
+    },
+    /// 升级前备份失败，数据库未做任何修改
+    Backup(String),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "数据库错误: {}", e),
+            MigrationError::NewerThanApp { found, supported } => write!(
+                f,
+                "数据库版本 {} 高于当前程序支持的版本 {}，请升级程序后再打开",
+                found, supported
+            ),
+            MigrationError::Backup(e) => write!(f, "升级数据库前备份失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+/// 将数据库升级到 SCHEMA_VERSION
+///
+/// 已有数据的数据库升级前会在同目录下备份，`db_path` 为 None 时（内存数据库）不备份。
+/// 每个步骤在单独的事务中执行，失败时停留在上一个成功的版本
+pub fn run_migrations(conn: &mut Connection, db_path: Option<&str>) -> Result<(), MigrationError> {
+    let current: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if current > SCHEMA_VERSION {
+        return Err(MigrationError::NewerThanApp {
+            found: current,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    if current == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // 重建表时删除旧表不能级联删除引用它的数据，外键约束在迁移完成后才启用
+    conn.pragma_update(None, "foreign_keys", false)?;
+
+    if let Some(db_path) = db_path {
+        if has_user_tables(conn)? {
+            backup(conn, db_path, current)?;
+        }
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        info!(
+            "升级数据库到版本 {}: {}",
+            migration.version, migration.description
+        );
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn has_user_tables(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+// 使用 VACUUM INTO 生成一致的数据库副本
+fn backup(conn: &Connection, db_path: &str, version: u32) -> Result<(), MigrationError> {
+    let backup_path = format!(
+        "{}.v{}-{}.bak",
+        db_path,
+        version,
+        Local::now().format("%Y%m%d%H%M%S")
+    );
+    if Path::new(&backup_path).exists() {
+        warn!("备份文件已存在，将被覆盖: {}", backup_path);
+        std::fs::remove_file(&backup_path).map_err(|e| MigrationError::Backup(e.to_string()))?;
+    }
+    conn.execute("VACUUM INTO ?", [&backup_path])
+        .map_err(|e| MigrationError::Backup(e.to_string()))?;
+    info!("数据库已备份到 {}", backup_path);
+    Ok(())
+}
+
+// 字段不存在时添加字段
+fn ensure_column(
+    tx: &Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        tx.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+// 版本 1 之前的程序没有记录版本号，下面的步骤都允许对应的表或字段已经存在
+
+fn create_base_tables(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS conversations (
+            id INTEGER PRIMARY KEY,
+            title TEXT NOT NULL,
+            last_message TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY,
+            conversation_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            FOREIGN KEY (conversation_id) REFERENCES conversations (id) ON DELETE CASCADE
+        );",
+    )
+}
+
+fn add_message_partial(tx: &Transaction) -> rusqlite::Result<()> {
+    ensure_column(tx, "messages", "partial", "INTEGER NOT NULL DEFAULT 0")
+}
+
+fn create_conversation_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS conversation_settings (
+            conversation_id INTEGER PRIMARY KEY,
+            model_name TEXT,
+            system_prompt TEXT,
+            temperature REAL,
+            top_p REAL,
+            num_ctx INTEGER,
+            seed INTEGER,
+            FOREIGN KEY (conversation_id) REFERENCES conversations (id) ON DELETE CASCADE
+        );",
+    )
+}
+
+// 使用 trigram 分词以支持中文等没有空格分隔的文本
+fn create_search_index(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            content,
+            content = 'messages',
+            content_rowid = 'id',
+            tokenize = 'trigram'
+        );
+        CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');",
+    )
+}
+
+// 消息按 parent_id 组成树，编辑或重新生成时产生分支，对话记录当前显示的分支末端
+fn add_message_branches(tx: &Transaction) -> rusqlite::Result<()> {
+    ensure_column(
+        tx,
+        "messages",
+        "parent_id",
+        "INTEGER REFERENCES messages (id) ON DELETE CASCADE",
+    )?;
+    ensure_column(tx, "conversations", "active_leaf_id", "INTEGER")?;
+    // 已有的消息按时间顺序连成一条链
+    tx.execute_batch(
+        "UPDATE messages SET parent_id = (
+            SELECT MAX(p.id) FROM messages p
+            WHERE p.conversation_id = messages.conversation_id AND p.id < messages.id
+        ) WHERE parent_id IS NULL;
+        UPDATE conversations SET active_leaf_id = (
+            SELECT MAX(id) FROM messages WHERE conversation_id = conversations.id
+        );
+        CREATE INDEX IF NOT EXISTS idx_messages_parent ON messages (parent_id);",
+    )
+}
+
+fn add_conversation_organization(tx: &Transaction) -> rusqlite::Result<()> {
+    ensure_column(tx, "conversations", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(
+        tx,
+        "conversations",
+        "archived",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS conversation_tags (
+            conversation_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (conversation_id, tag),
+            FOREIGN KEY (conversation_id) REFERENCES conversations (id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_conversation_tags_tag ON conversation_tags (tag);",
+    )
+}
+
+// 没有 AUTOINCREMENT 时 SQLite 会把最大的已删除ID分配给新行，
+// 前端、导出文件和进行中的生成仍在使用旧ID，需要重建表并复制数据
+fn use_autoincrement_ids(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE conversations_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            last_message TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            active_leaf_id INTEGER,
+            pinned INTEGER NOT NULL DEFAULT 0,
+            archived INTEGER NOT NULL DEFAULT 0
+        );
+        INSERT INTO conversations_new (id, title, last_message, timestamp, active_leaf_id, pinned, archived)
+            SELECT id, title, last_message, timestamp, active_leaf_id, pinned, archived FROM conversations;
+        DROP TABLE conversations;
+        ALTER TABLE conversations_new RENAME TO conversations;
+
+        CREATE TABLE messages_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            conversation_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            partial INTEGER NOT NULL DEFAULT 0,
+            parent_id INTEGER REFERENCES messages (id) ON DELETE CASCADE,
+            FOREIGN KEY (conversation_id) REFERENCES conversations (id) ON DELETE CASCADE
+        );
+        INSERT INTO messages_new (id, conversation_id, content, sender, timestamp, partial, parent_id)
+            SELECT id, conversation_id, content, sender, timestamp, partial, parent_id FROM messages;
+        DROP TABLE messages;
+        ALTER TABLE messages_new RENAME TO messages;
+        CREATE INDEX idx_messages_parent ON messages (parent_id);",
+    )?;
+    // 删除旧表时全文索引的触发器也被删除
+    create_search_index(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 引入版本号之前的程序创建的数据库结构
+    fn legacy_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE conversations (
+                id INTEGER PRIMARY KEY,
+                title TEXT NOT NULL,
+                last_message TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY,
+                conversation_id INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                sender TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                FOREIGN KEY (conversation_id) REFERENCES conversations (id) ON DELETE CASCADE
+            );
+            INSERT INTO conversations VALUES (1, '新对话', '你好!', 1);
+            INSERT INTO messages VALUES (1, 1, '欢迎使用聊天应用!', 'bot', 1);
+            INSERT INTO messages VALUES (2, 1, '介绍一下全文搜索', 'user', 2);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn upgrades_legacy_two_table_schema() {
+        let mut conn = legacy_database();
+        run_migrations(&mut conn, None).unwrap();
+
+        assert_eq!(user_version(&conn), SCHEMA_VERSION);
+        let partial: Vec<bool> = conn
+            .prepare("SELECT partial FROM messages ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(partial, vec![false, false]);
+
+        // 已有消息被加入全文索引
+        let hits: u32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH '\"全文搜索\"'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
+
+        // 已有消息连成一条分支，末端为最后一条消息
+        let parents: Vec<Option<u64>> = conn
+            .prepare("SELECT parent_id FROM messages ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(parents, vec![None, Some(1)]);
+        let leaf: u64 = conn
+            .query_row("SELECT active_leaf_id FROM conversations", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(leaf, 2);
+
+        // 删除最后一条消息后新消息不复用它的ID
+        conn.execute("DELETE FROM messages WHERE id = 2", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO messages (conversation_id, content, sender, timestamp) VALUES (1, '新消息', 'user', 3)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(conn.last_insert_rowid(), 3);
+        let hits: u32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH '新消息'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
+
+        // 再次运行不做任何修改
+        run_migrations(&mut conn, None).unwrap();
+        assert_eq!(user_version(&conn), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn backs_up_before_upgrading() {
+        let dir = std::env::temp_dir().join(format!("chat_box_migration_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("chat_database.db");
+        let db_path = db_path.to_str().unwrap();
+
+        let mut conn = Connection::open(db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE conversations (
+                id INTEGER PRIMARY KEY,
+                title TEXT NOT NULL,
+                last_message TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );",
+        )
+        .unwrap();
+        run_migrations(&mut conn, Some(db_path)).unwrap();
+
+        let backups: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        let backup = Connection::open(backups[0].path()).unwrap();
+        assert_eq!(user_version(&backup), 0);
+
+        drop(conn);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+
+        match run_migrations(&mut conn, None) {
+            Err(MigrationError::NewerThanApp { found, supported }) => {
+                assert_eq!(found, SCHEMA_VERSION + 1);
+                assert_eq!(supported, SCHEMA_VERSION);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
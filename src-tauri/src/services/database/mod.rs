@@ -0,0 +1,1121 @@
+use chrono::Utc;
+use log::{debug, error, info};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::models::{
+    Conversation, ConversationFilter, ConversationSettings, ConversationSort, Message,
+    MessageBranch, SearchHit,
+};
+
+mod migrations;
+mod pool;
+mod repository;
+pub use migrations::MigrationError;
+pub use pool::DatabasePool;
+pub use repository::ChatRepository;
+
+// 使用 UPSERT 而不是 INSERT OR REPLACE：REPLACE 删除旧行时不会触发删除触发器，会导致全文索引残留
+// 消息在树中的位置创建后不再改变，更新时不修改 parent_id
+const UPSERT_MESSAGE_SQL: &str =
+    "INSERT INTO messages (id, conversation_id, content, sender, timestamp, partial, parent_id)
+     VALUES (?, ?, ?, ?, ?, ?, ?)
+     ON CONFLICT (id) DO UPDATE SET
+        conversation_id = excluded.conversation_id,
+        content = excluded.content,
+        sender = excluded.sender,
+        timestamp = excluded.timestamp,
+        partial = excluded.partial";
+
+// 从对话的当前分支末端沿 parent_id 向上，得到当前显示的消息ID，?1 为对话ID
+const ACTIVE_PATH_CTE: &str = "WITH RECURSIVE path (id) AS (
+        SELECT active_leaf_id FROM conversations WHERE id = ?1 AND active_leaf_id IS NOT NULL
+        UNION ALL
+        SELECT m.parent_id FROM messages m JOIN path ON m.id = path.id
+        WHERE m.parent_id IS NOT NULL
+    )";
+
+// 标签以 JSON 数组的形式一起查出，c 为 conversations 表的别名
+const CONVERSATION_COLUMNS: &str =
+    "c.id, c.title, c.last_message, c.timestamp, c.pinned, c.archived,
+    (SELECT json_group_array(tag) FROM conversation_tags t WHERE t.conversation_id = c.id)";
+
+const MESSAGE_COLUMNS: &str = "id, conversation_id, content, sender, timestamp, partial, parent_id";
+
+// 短于 trigram 长度的关键词无法使用全文索引
+const MIN_FTS_TERM_CHARS: usize = 3;
+
+// 数据库被其他连接锁定时的最长等待时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct ChatDatabase {
+    conn: Connection,
+}
+
+impl ChatDatabase {
+    pub fn new(db_path: &str) -> std::result::Result<Self, MigrationError> {
+        info!("开始创建数据库");
+        // 确保目录存在
+        if let Some(parent) = Path::new(db_path).parent() {
+            fs::create_dir_all(parent).map_err(|_e| rusqlite::Error::ExecuteReturnedResults)?;
+        }
+
+        info!("Opening database at: {}", db_path);
+        let conn = Connection::open(db_path)?;
+        Self::init(conn, Some(db_path))
+    }
+
+    // 未启用数据库时使用内存数据库，消息只在本次运行期间保存
+    pub fn open_in_memory() -> std::result::Result<Self, MigrationError> {
+        info!("使用内存数据库");
+        Self::init(Connection::open_in_memory()?, None)
+    }
+
+    fn init(
+        mut conn: Connection,
+        db_path: Option<&str>,
+    ) -> std::result::Result<Self, MigrationError> {
+        // 创建或升级表结构
+        migrations::run_migrations(&mut conn, db_path)?;
+
+        // 启用外键约束
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+
+        if db_path.is_some() {
+            // WAL 模式下读连接不会被写入阻塞
+            let mode: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+            info!("数据库日志模式: {}", mode);
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+        }
+
+        Ok(ChatDatabase { conn })
+    }
+
+    // 打开只读连接，表结构由写连接负责创建和升级
+    pub fn open_reader(db_path: &str) -> Result<Self> {
+        let conn = Connection::open_with_flags(
+            db_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(ChatDatabase { conn })
+    }
+
+    // 新建对话，返回数据库分配的ID
+    pub fn insert_conversation(&mut self, conversation: &Conversation) -> Result<u64> {
+        let tx = self.conn.transaction()?;
+        let id = insert_conversation_row(&tx, conversation)?;
+        tx.commit()?;
+        debug!("新建对话: {}", id);
+        Ok(id)
+    }
+
+    // 返回对话是否存在
+    pub fn set_conversation_pinned(&mut self, conversation_id: u64, pinned: bool) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE conversations SET pinned = ? WHERE id = ?",
+            params![pinned, conversation_id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    // 返回对话是否存在
+    pub fn set_conversation_archived(
+        &mut self,
+        conversation_id: u64,
+        archived: bool,
+    ) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE conversations SET archived = ? WHERE id = ?",
+            params![archived, conversation_id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    // 用 tags 替换对话原有的全部标签，返回对话是否存在
+    pub fn set_conversation_tags(&mut self, conversation_id: u64, tags: &[String]) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM conversations WHERE id = ?)",
+            params![conversation_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(false);
+        }
+        tx.execute(
+            "DELETE FROM conversation_tags WHERE conversation_id = ?",
+            params![conversation_id],
+        )?;
+        insert_tags(&tx, conversation_id, tags)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    // 所有对话用过的标签，按名称排序
+    pub fn get_all_tags(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT tag FROM conversation_tags ORDER BY tag")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    // 在 parent_id 下新建消息并设为对话的当前分支末端，返回数据库分配的ID
+    pub fn insert_message(&mut self, message: &Message) -> Result<u64> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO messages (conversation_id, content, sender, timestamp, partial, parent_id)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                message.conversation_id,
+                message.content,
+                message.sender,
+                message.timestamp,
+                message.partial,
+                message.parent_id
+            ],
+        )?;
+        let id = tx.last_insert_rowid() as u64;
+        tx.execute(
+            "UPDATE conversations SET active_leaf_id = ? WHERE id = ?",
+            params![id, message.conversation_id],
+        )?;
+        tx.commit()?;
+        debug!("新建消息: {} 到对话: {}", id, message.conversation_id);
+        Ok(id)
+    }
+
+    // 在对话当前分支的末尾追加消息
+    pub fn append_message(&mut self, message: &Message) -> Result<Message> {
+        let parent_id = self.active_leaf(message.conversation_id)?;
+        let message = Message {
+            parent_id,
+            ..message.clone()
+        };
+        let id = self.insert_message(&message)?;
+        Ok(Message { id, ..message })
+    }
+
+    // 对话当前分支的最后一条消息
+    pub fn active_leaf(&self, conversation_id: u64) -> Result<Option<u64>> {
+        self.conn
+            .query_row(
+                "SELECT active_leaf_id FROM conversations WHERE id = ?",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+    }
+
+    pub fn set_active_leaf(&mut self, conversation_id: u64, leaf_id: Option<u64>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET active_leaf_id = ? WHERE id = ?",
+            params![leaf_id, conversation_id],
+        )?;
+        Ok(())
+    }
+
+    // 切换到包含指定消息的分支，该消息之后沿最新的回复走到末端，返回所属对话ID
+    pub fn switch_branch(&mut self, message_id: u64) -> Result<u64> {
+        let (conversation_id, leaf_id): (u64, u64) = self.conn.query_row(
+            // 后代的ID总是大于祖先，链上最大的ID就是末端
+            "WITH RECURSIVE chain (id) AS (
+                SELECT ?1
+                UNION ALL
+                SELECT (SELECT MAX(m.id) FROM messages m WHERE m.parent_id = chain.id)
+                FROM chain WHERE chain.id IS NOT NULL
+            )
+            SELECT conversation_id, (SELECT MAX(id) FROM chain) FROM messages WHERE id = ?1",
+            params![message_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        self.set_active_leaf(conversation_id, Some(leaf_id))?;
+        debug!("对话 {} 切换到分支末端 {}", conversation_id, leaf_id);
+        Ok(conversation_id)
+    }
+
+    pub fn get_message(&self, message_id: u64) -> Result<Option<Message>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM messages WHERE id = ?", MESSAGE_COLUMNS),
+                params![message_id],
+                message_from_row,
+            )
+            .optional()
+    }
+
+    // 当前分支上有多个版本的消息及其所有版本
+    pub fn get_message_branches(&self, conversation_id: u64) -> Result<Vec<MessageBranch>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
+            SELECT m.id, s.id FROM path
+            JOIN messages m ON m.id = path.id
+            JOIN messages s ON s.conversation_id = m.conversation_id AND s.parent_id IS m.parent_id
+            ORDER BY m.id, s.id",
+            ACTIVE_PATH_CTE
+        ))?;
+        let rows = stmt.query_map(params![conversation_id], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?))
+        })?;
+
+        let mut branches: Vec<MessageBranch> = Vec::new();
+        for row in rows {
+            let (message_id, sibling_id) = row?;
+            match branches.last_mut() {
+                Some(branch) if branch.message_id == message_id => branch.siblings.push(sibling_id),
+                _ => branches.push(MessageBranch {
+                    message_id,
+                    siblings: vec![sibling_id],
+                }),
+            }
+        }
+        branches.retain(|branch| branch.siblings.len() > 1);
+        Ok(branches)
+    }
+
+    // 保存消息
+    pub fn save_message(&mut self, message: &Message) -> Result<()> {
+        self.conn.execute(
+            UPSERT_MESSAGE_SQL,
+            params![
+                message.id,
+                message.conversation_id,
+                message.content,
+                message.sender,
+                message.timestamp,
+                message.partial,
+                message.parent_id
+            ],
+        )?;
+
+        debug!(
+            "保存消息: {} 到对话: {}",
+            message.id, message.conversation_id
+        );
+        Ok(())
+    }
+
+    // 没有任何对话时创建默认对话和欢迎消息，返回新建的对话ID
+    pub fn ensure_default_conversation(
+        &mut self,
+        title: &str,
+        welcome_message: &str,
+    ) -> Result<Option<u64>> {
+        if !self.get_all_conversations()?.is_empty() {
+            return Ok(None);
+        }
+
+        let now = Utc::now().timestamp_millis() as u64;
+        let conversation_id = self.insert_conversation(&Conversation {
+            id: 0,
+            title: title.to_string(),
+            last_message: "你好!".to_string(),
+            timestamp: now,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+        })?;
+        self.insert_message(&Message {
+            id: 0,
+            content: welcome_message.to_string(),
+            sender: "bot".to_string(),
+            timestamp: now,
+            conversation_id,
+            partial: false,
+            parent_id: None,
+        })?;
+        Ok(Some(conversation_id))
+    }
+
+    // 导入对话，对话和消息的ID由数据库重新分配，返回新的对话ID
+    pub fn import_conversation(
+        &mut self,
+        conversation: &Conversation,
+        messages: &[Message],
+        settings: Option<&ConversationSettings>,
+    ) -> Result<u64> {
+        let tx = self.conn.transaction()?;
+        let conversation_id = insert_conversation_row(&tx, conversation)?;
+
+        // 导入的消息按顺序连成一条分支
+        let mut parent_id: Option<u64> = None;
+        for message in messages {
+            tx.execute(
+                "INSERT INTO messages (conversation_id, content, sender, timestamp, partial, parent_id)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                params![
+                    conversation_id,
+                    message.content,
+                    message.sender,
+                    message.timestamp,
+                    message.partial,
+                    parent_id
+                ],
+            )?;
+            parent_id = Some(tx.last_insert_rowid() as u64);
+        }
+        tx.execute(
+            "UPDATE conversations SET active_leaf_id = ? WHERE id = ?",
+            params![parent_id, conversation_id],
+        )?;
+
+        if let Some(settings) = settings {
+            tx.execute(
+                "INSERT INTO conversation_settings
+                 (conversation_id, model_name, system_prompt, temperature, top_p, num_ctx, seed)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    conversation_id,
+                    settings.model_name,
+                    settings.system_prompt,
+                    settings.temperature,
+                    settings.top_p,
+                    settings.num_ctx,
+                    settings.seed
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        debug!("导入对话 {}，共 {} 条消息", conversation_id, messages.len());
+        Ok(conversation_id)
+    }
+
+    // 获取所有对话，包括已归档的
+    pub fn get_all_conversations(&self) -> Result<Vec<Conversation>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM conversations c ORDER BY c.timestamp DESC",
+            CONVERSATION_COLUMNS
+        ))?;
+
+        let rows = stmt.query_map([], conversation_from_row)?;
+
+        let mut conversations = Vec::new();
+        for row in rows {
+            conversations.push(row?);
+        }
+
+        info!("加载了 {} 个对话", conversations.len());
+        Ok(conversations)
+    }
+
+    // 按条件筛选对话，置顶的排在最前面
+    pub fn list_conversations(&self, filter: &ConversationFilter) -> Result<Vec<Conversation>> {
+        let order = match filter.sort {
+            ConversationSort::Recent => "c.timestamp DESC",
+            ConversationSort::Created => "c.id DESC",
+            ConversationSort::Title => "c.title COLLATE NOCASE ASC",
+        };
+        let tags = serde_json::to_string(&filter.tags)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM conversations c
+             WHERE c.archived = ?1 AND (?2 = 0 OR c.pinned = 1)
+               AND (SELECT COUNT(*) FROM conversation_tags t
+                    WHERE t.conversation_id = c.id AND t.tag IN (SELECT value FROM json_each(?3)))
+                   = json_array_length(?3)
+             ORDER BY c.pinned DESC, {}",
+            CONVERSATION_COLUMNS, order
+        ))?;
+
+        let rows = stmt.query_map(
+            params![filter.archived, filter.pinned_only, tags],
+            conversation_from_row,
+        )?;
+        rows.collect()
+    }
+
+    // 获取单个对话，不存在时返回 None
+    pub fn get_conversation(&self, conversation_id: u64) -> Result<Option<Conversation>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM conversations c WHERE c.id = ?",
+                    CONVERSATION_COLUMNS
+                ),
+                params![conversation_id],
+                conversation_from_row,
+            )
+            .optional()
+    }
+
+    // 分页获取对话当前分支上的消息，返回ID小于 before 的最近 limit 条，按时间先后排列；
+    // before 和 limit 都为空时返回整个分支
+    pub fn get_conversation_messages(
+        &self,
+        conversation_id: u64,
+        before: Option<u64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Message>> {
+        // 新消息的ID总是更大，按ID排序即为时间顺序
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
+            SELECT {} FROM messages
+            WHERE id IN (SELECT id FROM path) AND (?2 IS NULL OR id < ?2)
+            ORDER BY id DESC
+            LIMIT ?3",
+            ACTIVE_PATH_CTE, MESSAGE_COLUMNS
+        ))?;
+
+        // LIMIT -1 表示不限制数量
+        let limit = limit.map(i64::from).unwrap_or(-1);
+        let rows = stmt.query_map(params![conversation_id, before, limit], message_from_row)?;
+
+        let mut messages = rows.collect::<Result<Vec<_>>>()?;
+        messages.reverse();
+
+        debug!(
+            "加载了对话 {} 的 {} 条消息",
+            conversation_id,
+            messages.len()
+        );
+        Ok(messages)
+    }
+
+    // 更新对话的最后一条消息和时间
+    pub fn update_conversation_preview(
+        &mut self,
+        conversation_id: u64,
+        last_message: &str,
+        timestamp: u64,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET last_message = ?, timestamp = ? WHERE id = ?",
+            params![last_message, timestamp, conversation_id],
+        )?;
+        Ok(())
+    }
+
+    // 修改对话标题，expected 不为空时只在当前标题等于它时修改，返回是否修改了
+    pub fn update_conversation_title(
+        &mut self,
+        conversation_id: u64,
+        title: &str,
+        expected: Option<&str>,
+    ) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE conversations SET title = ?1 WHERE id = ?2 AND (?3 IS NULL OR title = ?3)",
+            params![title, conversation_id, expected],
+        )?;
+        Ok(changed > 0)
+    }
+
+    // 在同一事务中保存消息并更新所属对话
+    pub fn save_message_with_preview(&mut self, message: &Message, timestamp: u64) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            UPSERT_MESSAGE_SQL,
+            params![
+                message.id,
+                message.conversation_id,
+                message.content,
+                message.sender,
+                message.timestamp,
+                message.partial,
+                message.parent_id
+            ],
+        )?;
+        tx.execute(
+            "UPDATE conversations SET last_message = ?, timestamp = ? WHERE id = ?",
+            params![message.content, timestamp, message.conversation_id],
+        )?;
+        tx.commit()
+    }
+
+    // 消息、设置和标签通过外键级联删除，返回对话是否存在
+    pub fn delete_conversation(&mut self, conversation_id: u64) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM conversations WHERE id = ?",
+            params![conversation_id],
+        )?;
+        if deleted > 0 {
+            info!("删除对话及其消息: {}", conversation_id);
+        }
+        Ok(deleted > 0)
+    }
+
+    // 获取对话的模型设置，没有设置过时返回 None
+    pub fn get_conversation_settings(
+        &self,
+        conversation_id: u64,
+    ) -> Result<Option<ConversationSettings>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT conversation_id, model_name, system_prompt, temperature, top_p, num_ctx, seed
+             FROM conversation_settings WHERE conversation_id = ?",
+        )?;
+
+        let mut rows = stmt.query_map(params![conversation_id], |row| {
+            Ok(ConversationSettings {
+                conversation_id: row.get(0)?,
+                model_name: row.get(1)?,
+                system_prompt: row.get(2)?,
+                temperature: row.get(3)?,
+                top_p: row.get(4)?,
+                num_ctx: row.get(5)?,
+                seed: row.get(6)?,
+            })
+        })?;
+
+        rows.next().transpose()
+    }
+
+    // 保存对话的模型设置
+    pub fn save_conversation_settings(&mut self, settings: &ConversationSettings) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO conversation_settings
+             (conversation_id, model_name, system_prompt, temperature, top_p, num_ctx, seed)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                settings.conversation_id,
+                settings.model_name,
+                settings.system_prompt,
+                settings.temperature,
+                settings.top_p,
+                settings.num_ctx,
+                settings.seed
+            ],
+        )?;
+
+        debug!("保存对话 {} 的模型设置", settings.conversation_id);
+        Ok(())
+    }
+
+    // 在所有对话中搜索消息，摘要为转义后的 HTML，匹配部分用 <mark></mark> 标出
+    pub fn search_messages(&self, query: &str, limit: u32, offset: u32) -> Result<Vec<SearchHit>> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let hits = if terms
+            .iter()
+            .all(|term| term.chars().count() >= MIN_FTS_TERM_CHARS)
+        {
+            self.search_messages_fts(&terms, limit, offset)?
+        } else {
+            self.search_messages_like(&terms, limit, offset)?
+        };
+        debug!("搜索 \"{}\" 找到 {} 条消息", query, hits.len());
+        Ok(hits)
+    }
+
+    fn search_messages_fts(
+        &self,
+        terms: &[&str],
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>> {
+        // 每个关键词作为短语匹配，避免用户输入被解析为 FTS 查询语法
+        let fts_query = terms
+            .iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        let mut stmt = self.conn.prepare(
+            "SELECT m.id, m.conversation_id, c.title,
+                    snippet(messages_fts, 0, ?, ?, '…', 32),
+                    m.sender, m.timestamp
+             FROM messages_fts
+             JOIN messages m ON m.id = messages_fts.rowid
+             JOIN conversations c ON c.id = m.conversation_id
+             WHERE messages_fts MATCH ?
+             ORDER BY rank
+             LIMIT ? OFFSET ?",
+        )?;
+        let marks = (MARK_START.to_string(), MARK_END.to_string());
+        let rows = stmt.query_map(params![marks.0, marks.1, fts_query, limit, offset], |row| {
+            let snippet: String = row.get(3)?;
+            Ok(SearchHit {
+                message_id: row.get(0)?,
+                conversation_id: row.get(1)?,
+                conversation_title: row.get(2)?,
+                snippet: snippet_html(&snippet),
+                sender: row.get(4)?,
+                timestamp: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // 关键词过短时退回到 LIKE 逐条匹配，摘要在此处生成
+    fn search_messages_like(
+        &self,
+        terms: &[&str],
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>> {
+        let conditions = vec!["m.content LIKE ? ESCAPE '\\'"; terms.len()].join(" AND ");
+        let sql = format!(
+            "SELECT m.id, m.conversation_id, c.title, m.content, m.sender, m.timestamp
+             FROM messages m
+             JOIN conversations c ON c.id = m.conversation_id
+             WHERE {}
+             ORDER BY m.timestamp DESC
+             LIMIT ? OFFSET ?",
+            conditions
+        );
+
+        let mut values: Vec<rusqlite::types::Value> = terms
+            .iter()
+            .map(|term| {
+                let escaped = term
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                format!("%{}%", escaped).into()
+            })
+            .collect();
+        values.push(limit.into());
+        values.push(offset.into());
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            let content: String = row.get(3)?;
+            Ok(SearchHit {
+                message_id: row.get(0)?,
+                conversation_id: row.get(1)?,
+                conversation_title: row.get(2)?,
+                snippet: make_snippet(&content, terms, 32),
+                sender: row.get(4)?,
+                timestamp: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+// 插入对话及其标签，ID由数据库分配
+fn insert_conversation_row(tx: &rusqlite::Transaction, conversation: &Conversation) -> Result<u64> {
+    tx.execute(
+        "INSERT INTO conversations (title, last_message, timestamp, pinned, archived)
+         VALUES (?, ?, ?, ?, ?)",
+        params![
+            conversation.title,
+            conversation.last_message,
+            conversation.timestamp,
+            conversation.pinned,
+            conversation.archived
+        ],
+    )?;
+    let id = tx.last_insert_rowid() as u64;
+    insert_tags(tx, id, &conversation.tags)?;
+    Ok(id)
+}
+
+fn insert_tags(tx: &rusqlite::Transaction, conversation_id: u64, tags: &[String]) -> Result<()> {
+    let mut stmt =
+        tx.prepare("INSERT OR IGNORE INTO conversation_tags (conversation_id, tag) VALUES (?, ?)")?;
+    for tag in tags {
+        stmt.execute(params![conversation_id, tag])?;
+    }
+    Ok(())
+}
+
+fn conversation_from_row(row: &rusqlite::Row) -> Result<Conversation> {
+    let tags: String = row.get(6)?;
+    let mut tags: Vec<String> = serde_json::from_str(&tags).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    tags.sort();
+    Ok(Conversation {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        last_message: row.get(2)?,
+        timestamp: row.get(3)?,
+        pinned: row.get(4)?,
+        archived: row.get(5)?,
+        tags,
+    })
+}
+
+fn message_from_row(row: &rusqlite::Row) -> Result<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        conversation_id: row.get(1)?,
+        content: row.get(2)?,
+        sender: row.get(3)?,
+        timestamp: row.get(4)?,
+        partial: row.get(5)?,
+        parent_id: row.get(6)?,
+    })
+}
+
+// FTS 摘要中临时标记匹配部分的字符，转义后再换成 <mark></mark>
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+
+// 转义 FTS 生成的摘要，并把匹配标记换成 <mark></mark>
+fn snippet_html(raw: &str) -> String {
+    let mut html = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            _ => push_escaped(&mut html, c),
+        }
+    }
+    html
+}
+
+fn push_escaped(html: &mut String, c: char) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        _ => html.push(c),
+    }
+}
+
+// 截取第一个关键词附近的文本作为摘要，转义后标出所有关键词
+fn make_snippet(content: &str, terms: &[&str], max_chars: usize) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let lower: Vec<char> = content.to_lowercase().chars().collect();
+    // 大小写转换可能改变长度，这种情况下不做大小写无关匹配
+    let haystack = if lower.len() == chars.len() {
+        &lower
+    } else {
+        &chars
+    };
+    let needles: Vec<Vec<char>> = terms
+        .iter()
+        .map(|term| {
+            if lower.len() == chars.len() {
+                term.to_lowercase().chars().collect()
+            } else {
+                term.chars().collect()
+            }
+        })
+        .filter(|needle: &Vec<char>| !needle.is_empty())
+        .collect();
+
+    let find = |needle: &[char], from: usize| {
+        (from..=haystack.len().saturating_sub(needle.len()))
+            .find(|&i| haystack[i..].starts_with(needle))
+    };
+
+    let first = needles
+        .iter()
+        .filter_map(|needle| find(needle, 0))
+        .min()
+        .unwrap_or(0);
+    let start = first.saturating_sub(max_chars / 2);
+    let end = (start + max_chars).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut i = start;
+    while i < end {
+        match needles
+            .iter()
+            .find(|needle| haystack[i..].starts_with(needle))
+        {
+            Some(needle) => {
+                let stop = (i + needle.len()).min(chars.len());
+                snippet.push_str("<mark>");
+                for &c in &chars[i..stop] {
+                    push_escaped(&mut snippet, c);
+                }
+                snippet.push_str("</mark>");
+                i = stop;
+            }
+            None => {
+                push_escaped(&mut snippet, chars[i]);
+                i += 1;
+            }
+        }
+    }
+    if i < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> ChatDatabase {
+        let mut db = ChatDatabase::new(":memory:").unwrap();
+        db.insert_conversation(&Conversation {
+            id: 0,
+            title: "天气".to_string(),
+            last_message: String::new(),
+            timestamp: 1,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+        })
+        .unwrap();
+        for (id, content) in [(1, "今天北京的天气怎么样？"), (2, "Rust 的所有权规则")]
+        {
+            db.save_message(&Message {
+                id,
+                content: content.to_string(),
+                sender: "user".to_string(),
+                timestamp: id,
+                conversation_id: 1,
+                partial: false,
+                parent_id: None,
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn deleted_ids_are_not_reused() {
+        let mut db = test_db();
+        let conversation = Conversation {
+            id: 0,
+            title: "临时".to_string(),
+            last_message: String::new(),
+            timestamp: 2,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+        };
+        let deleted_id = db.insert_conversation(&conversation).unwrap();
+        let deleted_message = db
+            .append_message(&Message {
+                id: 0,
+                content: "会被删除".to_string(),
+                sender: "user".to_string(),
+                timestamp: 3,
+                conversation_id: deleted_id,
+                partial: false,
+                parent_id: None,
+            })
+            .unwrap();
+        db.delete_conversation(deleted_id).unwrap();
+
+        let id = db.insert_conversation(&conversation).unwrap();
+        assert!(id > deleted_id);
+        let message = db
+            .append_message(&Message {
+                id: 0,
+                content: "新的对话".to_string(),
+                sender: "user".to_string(),
+                timestamp: 4,
+                conversation_id: id,
+                partial: false,
+                parent_id: None,
+            })
+            .unwrap();
+        assert!(message.id > deleted_message.id);
+    }
+
+    #[test]
+    fn deleting_conversation_removes_messages_settings_and_tags() {
+        let mut db = test_db();
+        let id = db.get_all_conversations().unwrap()[0].id;
+        db.save_conversation_settings(&ConversationSettings {
+            conversation_id: id,
+            model_name: Some("llama3".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        db.set_conversation_tags(id, &["天气".to_string()]).unwrap();
+
+        assert!(db.delete_conversation(id).unwrap());
+        assert!(db
+            .get_conversation_messages(id, None, None)
+            .unwrap()
+            .is_empty());
+        assert!(db.get_conversation_settings(id).unwrap().is_none());
+        assert!(db.get_all_tags().unwrap().is_empty());
+        assert!(db.search_messages("天气", 10, 0).unwrap().is_empty());
+        // 对话已经不存在
+        assert!(!db.delete_conversation(id).unwrap());
+        assert!(!db.set_conversation_pinned(id, true).unwrap());
+        assert!(!db.set_conversation_tags(id, &[]).unwrap());
+    }
+
+    #[test]
+    fn search_matches_chinese_text() {
+        let db = test_db();
+
+        let hits = db.search_messages("北京的天气", 10, 0).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].conversation_title, "天气");
+        assert!(hits[0].snippet.contains("<mark>北京的天气</mark>"));
+
+        // 两个字的关键词走 LIKE 匹配
+        let hits = db.search_messages("天气", 10, 0).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.contains("<mark>天气</mark>"));
+    }
+
+    #[test]
+    fn search_snippet_is_escaped_html() {
+        let mut db = test_db();
+        let id = db.get_all_conversations().unwrap()[0].id;
+        db.append_message(&Message {
+            id: 0,
+            content: "<script>天气预报</script>".to_string(),
+            sender: "bot".to_string(),
+            timestamp: 10,
+            conversation_id: id,
+            partial: false,
+            parent_id: None,
+        })
+        .unwrap();
+
+        // FTS 和 LIKE 两种匹配生成的摘要都要转义
+        for query in ["天气预报", "预报"] {
+            let hits = db.search_messages(query, 10, 0).unwrap();
+            assert_eq!(hits.len(), 1);
+            assert!(!hits[0].snippet.contains("<script>"));
+            assert!(hits[0].snippet.contains("&lt;/script&gt;"));
+            assert!(hits[0].snippet.contains(&format!("<mark>{}</mark>", query)));
+        }
+    }
+
+    #[test]
+    fn search_index_follows_updates() {
+        let mut db = test_db();
+        db.save_message(&Message {
+            id: 2,
+            content: "借用检查器".to_string(),
+            sender: "user".to_string(),
+            timestamp: 2,
+            conversation_id: 1,
+            partial: false,
+            parent_id: Some(1),
+        })
+        .unwrap();
+
+        assert!(db.search_messages("所有权", 10, 0).unwrap().is_empty());
+        assert_eq!(db.search_messages("借用检查", 10, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn editing_creates_branch_and_history_follows_it() {
+        let mut db = ChatDatabase::new(":memory:").unwrap();
+        let conversation_id = db
+            .insert_conversation(&Conversation {
+                id: 0,
+                title: "分支".to_string(),
+                last_message: String::new(),
+                timestamp: 1,
+                pinned: false,
+                archived: false,
+                tags: Vec::new(),
+            })
+            .unwrap();
+        let message = |content: &str, sender: &str| Message {
+            id: 0,
+            content: content.to_string(),
+            sender: sender.to_string(),
+            timestamp: 1,
+            conversation_id,
+            partial: false,
+            parent_id: None,
+        };
+        let contents = |db: &ChatDatabase| -> Vec<String> {
+            db.get_conversation_messages(conversation_id, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.content)
+                .collect()
+        };
+
+        let welcome = db.append_message(&message("你好", "bot")).unwrap();
+        let question = db.append_message(&message("1+1等于几", "user")).unwrap();
+        db.append_message(&message("2", "bot")).unwrap();
+
+        // 编辑问题：在同一上级消息下新建版本
+        let edited = Message {
+            parent_id: question.parent_id,
+            ..message("2+2等于几", "user")
+        };
+        let edited_id = db.insert_message(&edited).unwrap();
+        db.append_message(&message("4", "bot")).unwrap();
+        assert_eq!(contents(&db), ["你好", "2+2等于几", "4"]);
+
+        let branches = db.get_message_branches(conversation_id).unwrap();
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].message_id, edited_id);
+        assert_eq!(branches[0].siblings, [question.id, edited_id]);
+
+        // 切回原来的问题时沿最新的回复走到末端
+        db.switch_branch(question.id).unwrap();
+        assert_eq!(contents(&db), ["你好", "1+1等于几", "2"]);
+
+        // 分页只在当前分支内进行
+        let page = db
+            .get_conversation_messages(conversation_id, Some(question.id + 1), Some(1))
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, question.id);
+        assert_eq!(
+            db.get_conversation_messages(conversation_id, Some(question.id), None)
+                .unwrap()[0]
+                .id,
+            welcome.id
+        );
+    }
+
+    #[test]
+    fn conversation_list_filters_and_pins() {
+        let mut db = ChatDatabase::new(":memory:").unwrap();
+        let mut insert = |title: &str, timestamp: u64, tags: &[&str]| {
+            db.insert_conversation(&Conversation {
+                id: 0,
+                title: title.to_string(),
+                last_message: String::new(),
+                timestamp,
+                pinned: false,
+                archived: false,
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+            })
+            .unwrap()
+        };
+        let work = insert("工作", 1, &["rust", "工作"]);
+        let notes = insert("笔记", 2, &["rust"]);
+        let old = insert("旧对话", 3, &[]);
+
+        db.set_conversation_pinned(work, true).unwrap();
+        db.set_conversation_archived(old, true).unwrap();
+
+        let ids = |filter: ConversationFilter| -> Vec<u64> {
+            db.list_conversations(&filter)
+                .unwrap()
+                .iter()
+                .map(|c| c.id)
+                .collect()
+        };
+        // 默认隐藏归档的对话，置顶的排在最前面
+        assert_eq!(ids(ConversationFilter::default()), [work, notes]);
+        assert_eq!(
+            ids(ConversationFilter {
+                archived: true,
+                ..Default::default()
+            }),
+            [old]
+        );
+        assert_eq!(
+            ids(ConversationFilter {
+                tags: vec!["rust".to_string(), "工作".to_string()],
+                ..Default::default()
+            }),
+            [work]
+        );
+
+        db.set_conversation_tags(notes, &["笔记".to_string()])
+            .unwrap();
+        let notes = db.get_conversation(notes).unwrap().unwrap();
+        assert_eq!(notes.tags, ["笔记"]);
+        assert_eq!(db.get_all_tags().unwrap(), ["rust", "工作", "笔记"]);
+    }
+}
@@ -0,0 +1,142 @@
+use super::{ChatDatabase, MigrationError};
+use log::info;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// 只读连接数量，WAL 模式下读取不会等待正在写入的连接
+const READER_CONNECTIONS: usize = 4;
+
+/// 数据库连接池：一个写连接和若干只读连接，所有操作都在阻塞线程池中执行，
+/// 不占用异步运行时的线程
+#[derive(Clone)]
+pub struct DatabasePool {
+    writer: Arc<Mutex<ChatDatabase>>,
+    // 内存数据库无法被多个连接共享，此时为空，读取也使用写连接
+    readers: Arc<Vec<Mutex<ChatDatabase>>>,
+    next_reader: Arc<AtomicUsize>,
+}
+
+impl DatabasePool {
+    pub fn open(db_path: &str) -> Result<Self, MigrationError> {
+        // 写连接负责迁移，之后再打开只读连接
+        let writer = ChatDatabase::new(db_path)?;
+        let readers = (0..READER_CONNECTIONS)
+            .map(|_| ChatDatabase::open_reader(db_path).map(Mutex::new))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        info!("数据库连接池已打开，{} 个只读连接", readers.len());
+        Ok(Self::from_parts(writer, readers))
+    }
+
+    pub fn open_in_memory() -> Result<Self, MigrationError> {
+        Ok(Self::from_parts(
+            ChatDatabase::open_in_memory()?,
+            Vec::new(),
+        ))
+    }
+
+    fn from_parts(writer: ChatDatabase, readers: Vec<Mutex<ChatDatabase>>) -> Self {
+        DatabasePool {
+            writer: Arc::new(Mutex::new(writer)),
+            readers: Arc::new(readers),
+            next_reader: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// 在只读连接上执行查询
+    pub async fn read<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&ChatDatabase) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.clone();
+        run_blocking(move || pool.with_reader(f)).await
+    }
+
+    fn with_reader<T>(&self, f: impl FnOnce(&ChatDatabase) -> T) -> T {
+        if self.readers.is_empty() {
+            return f(&self.writer.lock().unwrap());
+        }
+        // 优先使用空闲的连接，全部忙碌时排队等待轮到的那个
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        let count = self.readers.len();
+        for i in 0..count {
+            if let Ok(reader) = self.readers[(start + i) % count].try_lock() {
+                return f(&reader);
+            }
+        }
+        f(&self.readers[start % count].lock().unwrap())
+    }
+
+    /// 在写连接上执行修改，写操作之间互相排队
+    pub async fn write<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut ChatDatabase) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let writer = self.writer.clone();
+        run_blocking(move || f(&mut writer.lock().unwrap())).await
+    }
+
+    /// 在当前线程直接使用写连接，只用于启动时等还没有异步运行时可用的场合
+    pub fn write_blocking<T>(
+        &self,
+        f: impl FnOnce(&mut ChatDatabase) -> rusqlite::Result<T>,
+    ) -> rusqlite::Result<T> {
+        f(&mut self.writer.lock().unwrap())
+    }
+}
+
+async fn run_blocking<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce() -> rusqlite::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("数据库任务异常退出: {}", e))?
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Conversation;
+
+    #[tokio::test]
+    async fn readers_see_writes_and_reject_changes() {
+        let dir = std::env::temp_dir().join(format!("chat_box_pool_{}", std::process::id()));
+        let db_path = dir.join("chat_database.db");
+        let pool = DatabasePool::open(db_path.to_str().unwrap()).unwrap();
+
+        let id = pool
+            .write(|db| {
+                db.insert_conversation(&Conversation {
+                    id: 0,
+                    title: "池".to_string(),
+                    last_message: String::new(),
+                    timestamp: 1,
+                    pinned: false,
+                    archived: false,
+                    tags: Vec::new(),
+                })
+            })
+            .await
+            .unwrap();
+
+        // 写连接被占用时读取仍然可以完成
+        {
+            let _writer = pool.writer.lock().unwrap();
+            let conversation = pool.with_reader(|db| db.get_conversation(id)).unwrap();
+            assert_eq!(conversation.unwrap().title, "池");
+        }
+
+        // 只读连接不能修改数据
+        let result = pool.with_reader(|db| {
+            db.conn
+                .execute("DELETE FROM conversations WHERE id = ?", [id])
+        });
+        assert!(result.is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
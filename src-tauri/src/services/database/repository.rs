@@ -0,0 +1,333 @@
+use super::{ChatDatabase, DatabasePool};
+use crate::models::{
+    Conversation, ConversationFilter, ConversationSettings, Message, MessageBranch, SearchHit,
+};
+use chrono::Utc;
+use log::info;
+use std::sync::{Arc, RwLock};
+
+/// 对话和消息的读写入口，命令层不直接持有数据库连接
+#[derive(Clone)]
+pub struct ChatRepository {
+    pool: Arc<RwLock<Option<DatabasePool>>>,
+}
+
+impl ChatRepository {
+    pub fn new(pool: Arc<RwLock<Option<DatabasePool>>>) -> Self {
+        Self { pool }
+    }
+
+    fn pool(&self) -> Result<DatabasePool, String> {
+        self.pool
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| "数据库未初始化".to_string())
+    }
+
+    /// 在只读连接上执行查询
+    pub async fn read<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&ChatDatabase) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.pool()?.read(f).await
+    }
+
+    /// 在写连接上执行修改
+    pub async fn write<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut ChatDatabase) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.pool()?.write(f).await
+    }
+
+    // 所有对话，最近活动的在前
+    pub async fn conversations(&self) -> Result<Vec<Conversation>, String> {
+        self.read(|db| db.get_all_conversations()).await
+    }
+
+    // 按条件筛选的对话列表，置顶的在前
+    pub async fn list_conversations(
+        &self,
+        filter: ConversationFilter,
+    ) -> Result<Vec<Conversation>, String> {
+        self.read(move |db| db.list_conversations(&filter)).await
+    }
+
+    pub async fn conversation(&self, conversation_id: u64) -> Result<Option<Conversation>, String> {
+        self.read(move |db| db.get_conversation(conversation_id))
+            .await
+    }
+
+    // 修改对话后返回最新的状态，f 返回对话是否存在，不存在时返回错误
+    async fn update_conversation<F>(
+        &self,
+        conversation_id: u64,
+        f: F,
+    ) -> Result<Conversation, String>
+    where
+        F: FnOnce(&mut ChatDatabase) -> rusqlite::Result<bool> + Send + 'static,
+    {
+        if !self.write(f).await? {
+            return Err(format!("对话 {} 不存在", conversation_id));
+        }
+        self.conversation(conversation_id)
+            .await?
+            .ok_or_else(|| format!("对话 {} 不存在", conversation_id))
+    }
+
+    pub async fn rename_conversation(
+        &self,
+        conversation_id: u64,
+        title: String,
+    ) -> Result<Conversation, String> {
+        self.update_conversation(conversation_id, move |db| {
+            db.update_conversation_title(conversation_id, &title, None)
+        })
+        .await
+    }
+
+    pub async fn set_pinned(
+        &self,
+        conversation_id: u64,
+        pinned: bool,
+    ) -> Result<Conversation, String> {
+        self.update_conversation(conversation_id, move |db| {
+            db.set_conversation_pinned(conversation_id, pinned)
+        })
+        .await
+    }
+
+    pub async fn set_archived(
+        &self,
+        conversation_id: u64,
+        archived: bool,
+    ) -> Result<Conversation, String> {
+        self.update_conversation(conversation_id, move |db| {
+            db.set_conversation_archived(conversation_id, archived)
+        })
+        .await
+    }
+
+    pub async fn set_tags(
+        &self,
+        conversation_id: u64,
+        tags: Vec<String>,
+    ) -> Result<Conversation, String> {
+        self.update_conversation(conversation_id, move |db| {
+            db.set_conversation_tags(conversation_id, &tags)
+        })
+        .await
+    }
+
+    // 所有用过的标签
+    pub async fn tags(&self) -> Result<Vec<String>, String> {
+        self.read(|db| db.get_all_tags()).await
+    }
+
+    // 新建对话，ID由数据库分配
+    pub async fn create_conversation(
+        &self,
+        conversation: Conversation,
+    ) -> Result<Conversation, String> {
+        self.write(move |db| {
+            let id = db.insert_conversation(&conversation)?;
+            Ok(Conversation { id, ..conversation })
+        })
+        .await
+    }
+
+    // 标题仍为 expected 时改为 title，返回修改后的对话；标题已被改过时返回 None
+    pub async fn replace_title(
+        &self,
+        conversation_id: u64,
+        expected: String,
+        title: String,
+    ) -> Result<Option<Conversation>, String> {
+        let changed = self
+            .write(move |db| db.update_conversation_title(conversation_id, &title, Some(&expected)))
+            .await?;
+        if !changed {
+            return Ok(None);
+        }
+        self.conversation(conversation_id).await
+    }
+
+    // 删除对话及其消息和设置
+    pub async fn delete_conversation(&self, conversation_id: u64) -> Result<(), String> {
+        let deleted = self
+            .write(move |db| db.delete_conversation(conversation_id))
+            .await?;
+        if !deleted {
+            return Err(format!("对话 {} 不存在", conversation_id));
+        }
+        Ok(())
+    }
+
+    // 分页获取消息，见 ChatDatabase::get_conversation_messages
+    pub async fn messages(
+        &self,
+        conversation_id: u64,
+        before: Option<u64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Message>, String> {
+        self.read(move |db| db.get_conversation_messages(conversation_id, before, limit))
+            .await
+    }
+
+    // 对话的全部消息，用作模型的上下文
+    pub async fn history(&self, conversation_id: u64) -> Result<Vec<Message>, String> {
+        self.messages(conversation_id, None, None).await
+    }
+
+    // 在当前分支末尾新建消息，ID由数据库分配
+    pub async fn append_message(&self, message: Message) -> Result<Message, String> {
+        self.write(move |db| db.append_message(&message)).await
+    }
+
+    pub async fn message(&self, message_id: u64) -> Result<Message, String> {
+        self.read(move |db| db.get_message(message_id))
+            .await?
+            .ok_or_else(|| format!("消息 {} 不存在", message_id))
+    }
+
+    // 编辑用户消息：在同一条上级消息下新建一个版本并切换过去，原来的分支保留
+    pub async fn edit_user_message(
+        &self,
+        message_id: u64,
+        content: String,
+    ) -> Result<Message, String> {
+        let original = self.message(message_id).await?;
+        if original.sender != "user" {
+            return Err("只能编辑用户发送的消息".to_string());
+        }
+
+        let message = Message {
+            id: 0,
+            content,
+            timestamp: Utc::now().timestamp_millis() as u64,
+            partial: false,
+            ..original
+        };
+        self.write(move |db| {
+            let id = db.insert_message(&message)?;
+            db.update_conversation_preview(
+                message.conversation_id,
+                &message.content,
+                message.timestamp,
+            )?;
+            Ok(Message { id, ..message })
+        })
+        .await
+    }
+
+    // 把当前分支退回到机器人回复的上级消息，之后生成的回复会成为该回复的另一个版本，
+    // 返回所属对话ID和退回前的分支末尾
+    pub async fn rewind_before_reply(&self, message_id: u64) -> Result<(u64, Option<u64>), String> {
+        let message = self.message(message_id).await?;
+        if message.sender != "bot" {
+            return Err("只能重新生成机器人的回复".to_string());
+        }
+        let parent_id = message
+            .parent_id
+            .ok_or_else(|| "这条回复之前没有可以发送给模型的消息".to_string())?;
+
+        let conversation_id = message.conversation_id;
+        let previous = self
+            .write(move |db| {
+                let previous = db.active_leaf(conversation_id)?;
+                db.set_active_leaf(conversation_id, Some(parent_id))?;
+                Ok(previous)
+            })
+            .await?;
+        Ok((conversation_id, previous))
+    }
+
+    // 直接设置对话的分支末尾，例如重新生成失败后恢复原来的分支
+    pub async fn set_active_leaf(
+        &self,
+        conversation_id: u64,
+        leaf_id: Option<u64>,
+    ) -> Result<(), String> {
+        self.write(move |db| db.set_active_leaf(conversation_id, leaf_id))
+            .await
+    }
+
+    // 切换到包含指定消息的分支，返回所属对话ID
+    pub async fn switch_branch(&self, message_id: u64) -> Result<u64, String> {
+        self.message(message_id).await?;
+        self.write(move |db| db.switch_branch(message_id)).await
+    }
+
+    pub async fn branches(&self, conversation_id: u64) -> Result<Vec<MessageBranch>, String> {
+        self.read(move |db| db.get_message_branches(conversation_id))
+            .await
+    }
+
+    // 更新已有消息，例如生成过程中写入的部分回复
+    pub async fn save_message(&self, message: Message) -> Result<(), String> {
+        self.write(move |db| db.save_message(&message)).await
+    }
+
+    // 保存消息并把它设为对话的最后一条消息
+    pub async fn save_message_with_preview(&self, message: Message) -> Result<(), String> {
+        let now = Utc::now().timestamp_millis() as u64;
+        self.write(move |db| db.save_message_with_preview(&message, now))
+            .await
+    }
+
+    pub async fn update_conversation_preview(
+        &self,
+        conversation_id: u64,
+        last_message: String,
+        timestamp: u64,
+    ) -> Result<(), String> {
+        self.write(move |db| {
+            db.update_conversation_preview(conversation_id, &last_message, timestamp)
+        })
+        .await
+    }
+
+    pub async fn conversation_settings(
+        &self,
+        conversation_id: u64,
+    ) -> Result<Option<ConversationSettings>, String> {
+        self.read(move |db| db.get_conversation_settings(conversation_id))
+            .await
+    }
+
+    pub async fn save_conversation_settings(
+        &self,
+        settings: ConversationSettings,
+    ) -> Result<(), String> {
+        self.write(move |db| db.save_conversation_settings(&settings))
+            .await
+    }
+
+    pub async fn search_messages(
+        &self,
+        query: String,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, String> {
+        self.read(move |db| db.search_messages(&query, limit, offset))
+            .await
+    }
+
+    // 数据库中没有任何对话时创建默认对话和欢迎消息
+    pub async fn ensure_default_conversation(
+        &self,
+        title: String,
+        welcome_message: String,
+    ) -> Result<(), String> {
+        let created = self
+            .write(move |db| db.ensure_default_conversation(&title, &welcome_message))
+            .await?;
+        if let Some(conversation_id) = created {
+            info!("创建了默认对话 {}", conversation_id);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use crate::models::{Conversation, ConversationSettings, Message};
+use crate::services::database::ChatDatabase;
+use chrono::{Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// JSON 导出文件的格式标识
+pub const EXPORT_FORMAT: &str = "chat_box.conversations";
+/// JSON 导出格式的版本，字段含义变化时递增，导入时按版本处理
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[serde(alias = "md")]
+    Markdown,
+    Json,
+    #[serde(alias = "htm")]
+    Html,
+}
+
+/// JSON 导出文件（version 1）
+///
+/// ```json
+/// {
+///   "format": "chat_box.conversations",
+///   "version": 1,
+///   "exported_at": 1718000000000,
+///   "conversations": [
+///     {
+///       "id": 1,
+///       "title": "新对话",
+///       "last_message": "你好!",
+///       "timestamp": 1718000000000,
+///       "pinned": false,
+///       "archived": false,
+///       "tags": ["工作"],
+///       "settings": null,
+///       "messages": [
+///         {
+///           "id": 1718000000001,
+///           "content": "你好!",
+///           "sender": "user",
+///           "timestamp": 1718000000001,
+///           "conversation_id": 1,
+///           "partial": false,
+///           "parent_id": null
+///         }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// 时间均为毫秒时间戳；`sender` 为 `user` 或 `bot`；`settings` 与
+/// `get_conversation_settings` 返回的结构相同，没有单独设置时为 null。
+/// `messages` 只包含对话当前分支上的消息。
+/// `pinned`、`archived` 和 `tags` 是 version 1 中后来加入的可选字段，
+/// 较早的导出文件中没有这些字段，导入时分别按 false、false 和空列表处理
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportDocument {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: u64,
+    pub conversations: Vec<ExportedConversation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportedConversation {
+    #[serde(flatten)]
+    pub conversation: Conversation,
+    #[serde(default)]
+    pub settings: Option<ConversationSettings>,
+    pub messages: Vec<Message>,
+}
+
+impl ExportDocument {
+    pub fn new(conversations: Vec<ExportedConversation>) -> Self {
+        Self {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            exported_at: Utc::now().timestamp_millis() as u64,
+            conversations,
+        }
+    }
+}
+
+/// 从数据库读取对话及其消息和设置，对话不存在时返回 None
+pub fn collect_conversation(
+    db: &ChatDatabase,
+    conversation_id: u64,
+) -> rusqlite::Result<Option<ExportedConversation>> {
+    let conversation = match db.get_conversation(conversation_id)? {
+        Some(conversation) => conversation,
+        None => return Ok(None),
+    };
+    Ok(Some(ExportedConversation {
+        settings: db.get_conversation_settings(conversation_id)?,
+        messages: db.get_conversation_messages(conversation_id, None, None)?,
+        conversation,
+    }))
+}
+
+/// 按格式生成导出文件内容
+pub fn render(
+    format: ExportFormat,
+    conversations: &[ExportedConversation],
+) -> Result<String, String> {
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&ExportDocument::new(conversations.to_vec()))
+                .map_err(|e| format!("序列化导出内容失败: {}", e))
+        }
+        ExportFormat::Markdown => Ok(to_markdown(conversations)),
+        ExportFormat::Html => Ok(to_html(conversations)),
+    }
+}
+
+fn sender_label(sender: &str) -> &str {
+    match sender {
+        "user" => "用户",
+        "bot" => "助手",
+        other => other,
+    }
+}
+
+fn format_time(timestamp: u64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// 消息内容中的文本段和代码块
+enum Block<'a> {
+    Text(String),
+    Code {
+        info: &'a str,
+        fence: &'a str,
+        body: String,
+        closed: bool,
+    },
+}
+
+// 行首（最多三个空格缩进）连续三个以上的 ` 或 ~ 为代码块标记
+fn fence_marker(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let first = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|c| *c == first).count();
+    (len >= 3).then(|| &trimmed[..len])
+}
+
+fn parse_blocks(content: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    let mut code: Option<(&str, &str, String)> = None;
+
+    for line in content.lines() {
+        match code.as_mut() {
+            Some((fence, _, body)) => {
+                let closes = fence_marker(line).is_some_and(|marker| {
+                    marker.starts_with(*fence)
+                        && line.trim_start_matches(' ')[marker.len()..]
+                            .trim()
+                            .is_empty()
+                });
+                if closes {
+                    let (fence, info, body) = code.take().unwrap();
+                    blocks.push(Block::Code {
+                        info,
+                        fence,
+                        body,
+                        closed: true,
+                    });
+                } else {
+                    body.push_str(line);
+                    body.push('\n');
+                }
+            }
+            None => match fence_marker(line) {
+                Some(fence) => {
+                    if !text.is_empty() {
+                        blocks.push(Block::Text(std::mem::take(&mut text)));
+                    }
+                    let info = line.trim_start_matches(' ')[fence.len()..].trim();
+                    code = Some((fence, info, String::new()));
+                }
+                None => {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            },
+        }
+    }
+
+    if let Some((fence, info, body)) = code {
+        blocks.push(Block::Code {
+            info,
+            fence,
+            body,
+            closed: false,
+        });
+    }
+    if !text.is_empty() {
+        blocks.push(Block::Text(text));
+    }
+    blocks
+}
+
+fn to_markdown(conversations: &[ExportedConversation]) -> String {
+    let mut out = String::new();
+    for (index, exported) in conversations.iter().enumerate() {
+        if index > 0 {
+            out.push_str("\n---\n\n");
+        }
+        out.push_str(&format!("# {}\n\n", exported.conversation.title));
+        out.push_str(&format!(
+            "> 最后更新于 {} · 共 {} 条消息\n\n",
+            format_time(exported.conversation.timestamp),
+            exported.messages.len()
+        ));
+
+        for message in &exported.messages {
+            out.push_str(&format!(
+                "## {} · {}{}\n\n",
+                sender_label(&message.sender),
+                format_time(message.timestamp),
+                if message.partial {
+                    "（未完成）"
+                } else {
+                    ""
+                }
+            ));
+            // 内容原样输出，被中断的回复可能停在代码块中间，需要补上结束标记
+            let content = message.content.trim_end();
+            out.push_str(content);
+            out.push('\n');
+            if let Some(Block::Code {
+                fence,
+                closed: false,
+                ..
+            }) = parse_blocks(content).last()
+            {
+                out.push_str(fence);
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn content_to_html(content: &str) -> String {
+    let mut html = String::new();
+    for block in parse_blocks(content) {
+        match block {
+            Block::Text(text) => {
+                for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+                    html.push_str("<p>");
+                    html.push_str(&escape_html(paragraph).replace('\n', "<br>\n"));
+                    html.push_str("</p>\n");
+                }
+            }
+            Block::Code { info, body, .. } => {
+                let language = info.split_whitespace().next().unwrap_or("");
+                if language.is_empty() {
+                    html.push_str("<pre><code>");
+                } else {
+                    html.push_str(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        escape_html(language)
+                    ));
+                }
+                html.push_str(&escape_html(&body));
+                html.push_str("</code></pre>\n");
+            }
+        }
+    }
+    html
+}
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,'PingFang SC','Microsoft YaHei',sans-serif;max-width:860px;margin:0 auto;padding:24px;color:#303133;background:#f5f7fa}
+section{margin-bottom:48px}
+h1{font-size:22px;border-bottom:1px solid #dcdfe6;padding-bottom:8px}
+.meta{color:#909399;font-size:13px}
+.message{background:#fff;border-radius:8px;padding:12px 16px;margin:12px 0;box-shadow:0 1px 3px rgba(0,0,0,.08)}
+.message.user{background:#ecf5ff}
+.message header{color:#909399;font-size:12px;margin-bottom:6px}
+.partial{color:#e6a23c}
+pre{background:#282c34;color:#abb2bf;padding:12px;border-radius:6px;overflow-x:auto}
+code{font-family:Menlo,Consolas,monospace;font-size:13px}";
+
+fn to_html(conversations: &[ExportedConversation]) -> String {
+    let title = match conversations {
+        [single] => single.conversation.title.clone(),
+        _ => "对话记录".to_string(),
+    };
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+    out.push_str(&format!(
+        "<style>\n{}\n</style>\n</head>\n<body>\n",
+        HTML_STYLE
+    ));
+
+    for exported in conversations {
+        out.push_str("<section>\n");
+        out.push_str(&format!(
+            "<h1>{}</h1>\n<p class=\"meta\">最后更新于 {} · 共 {} 条消息</p>\n",
+            escape_html(&exported.conversation.title),
+            format_time(exported.conversation.timestamp),
+            exported.messages.len()
+        ));
+        for message in &exported.messages {
+            out.push_str(&format!(
+                "<article class=\"message {}\">\n<header>{} · {}{}</header>\n",
+                escape_html(&message.sender),
+                escape_html(sender_label(&message.sender)),
+                format_time(message.timestamp),
+                if message.partial {
+                    " <span class=\"partial\">（未完成）</span>"
+                } else {
+                    ""
+                }
+            ));
+            out.push_str(&content_to_html(&message.content));
+            out.push_str("</article>\n");
+        }
+        out.push_str("</section>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<ExportedConversation> {
+        vec![ExportedConversation {
+            conversation: Conversation {
+                id: 1,
+                title: "代码 <示例>".to_string(),
+                last_message: String::new(),
+                timestamp: 1,
+                pinned: false,
+                archived: false,
+                tags: Vec::new(),
+            },
+            settings: None,
+            messages: vec![
+                Message {
+                    id: 2,
+                    content: "```rust\nfn main() {}\n```".to_string(),
+                    sender: "user".to_string(),
+                    timestamp: 2,
+                    conversation_id: 1,
+                    partial: false,
+                    parent_id: None,
+                },
+                Message {
+                    id: 3,
+                    content: "示例：\n\n```python\nprint('<hi>')".to_string(),
+                    sender: "bot".to_string(),
+                    timestamp: 3,
+                    conversation_id: 1,
+                    partial: true,
+                    parent_id: Some(2),
+                },
+            ],
+        }]
+    }
+
+    #[test]
+    fn json_export_round_trips() {
+        let json = render(ExportFormat::Json, &sample()).unwrap();
+        let document: ExportDocument = serde_json::from_str(&json).unwrap();
+        assert_eq!(document.format, EXPORT_FORMAT);
+        assert_eq!(document.version, EXPORT_VERSION);
+        assert_eq!(document.conversations[0].conversation.title, "代码 <示例>");
+        assert_eq!(
+            document.conversations[0].messages[1].content,
+            sample()[0].messages[1].content
+        );
+        assert!(document.conversations[0].messages[1].partial);
+    }
+
+    #[test]
+    fn markdown_closes_unfinished_code_block() {
+        let markdown = render(ExportFormat::Markdown, &sample()).unwrap();
+        assert!(markdown.contains("```rust\nfn main() {}\n```\n"));
+        assert!(markdown.contains("print('<hi>')\n```\n"));
+        assert_eq!(markdown.matches("```").count(), 4);
+    }
+
+    #[test]
+    fn html_escapes_content() {
+        let html = render(ExportFormat::Html, &sample()).unwrap();
+        assert!(html.contains("<title>代码 &lt;示例&gt;</title>"));
+        assert!(html.contains(
+            "<pre><code class=\"language-python\">print(&#39;&lt;hi&gt;&#39;)\n</code></pre>"
+        ));
+    }
+}
@@ -0,0 +1,456 @@
+use crate::models::{Conversation, Message, SkippedItem};
+use crate::services::export::{
+    ExportDocument, ExportedConversation, EXPORT_FORMAT, EXPORT_VERSION,
+};
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// 解析后的导入内容，消息和对话的 id 在写入数据库时重新分配
+pub struct ParsedImport {
+    /// 识别出的来源: chat_box / chatgpt / open_webui
+    pub source: &'static str,
+    pub conversations: Vec<ExportedConversation>,
+    pub skipped: Vec<SkippedItem>,
+}
+
+/// 识别文件格式并转换为本程序的对话结构
+pub fn parse_import(content: &str) -> Result<ParsedImport, String> {
+    let value: Value =
+        serde_json::from_str(content).map_err(|e| format!("导入文件不是有效的 JSON: {}", e))?;
+
+    if value.get("format").and_then(Value::as_str) == Some(EXPORT_FORMAT) {
+        return parse_own_export(value);
+    }
+
+    // ChatGPT 和 Open WebUI 导出的都是对话数组，Open WebUI 单个对话导出为对象
+    let items = match value {
+        Value::Array(items) => items,
+        item @ Value::Object(_) if item.get("chat").is_some() => vec![item],
+        _ => return Err("无法识别的导入文件格式".to_string()),
+    };
+    let source = match items.first() {
+        Some(item) if item.get("mapping").is_some() => "chatgpt",
+        Some(item) if item.get("chat").is_some() => "open_webui",
+        Some(_) => return Err("无法识别的导入文件格式".to_string()),
+        None => return Err("导入文件中没有对话".to_string()),
+    };
+
+    let mut parsed = ParsedImport {
+        source,
+        conversations: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for (index, item) in items.iter().enumerate() {
+        let result = if source == "chatgpt" {
+            parse_chatgpt_conversation(item, index, &mut parsed.skipped)
+        } else {
+            parse_open_webui_chat(item, index, &mut parsed.skipped)
+        };
+        let title = item_title(item, index);
+        match result {
+            Ok(conversation) if conversation.messages.is_empty() => {
+                parsed
+                    .skipped
+                    .push(skipped(&title, None, "对话中没有可导入的消息"));
+            }
+            Ok(conversation) => parsed.conversations.push(conversation),
+            Err(reason) => parsed.skipped.push(skipped(&title, None, &reason)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_own_export(value: Value) -> Result<ParsedImport, String> {
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > EXPORT_VERSION as u64 {
+        return Err(format!(
+            "导出文件版本 {} 高于当前程序支持的版本 {}",
+            version, EXPORT_VERSION
+        ));
+    }
+    let document: ExportDocument =
+        serde_json::from_value(value).map_err(|e| format!("解析导出文件失败: {}", e))?;
+    Ok(ParsedImport {
+        source: "chat_box",
+        conversations: document.conversations,
+        skipped: Vec::new(),
+    })
+}
+
+fn skipped(conversation: &str, message: Option<&str>, reason: &str) -> SkippedItem {
+    SkippedItem {
+        conversation: conversation.to_string(),
+        message: message.map(str::to_string),
+        reason: reason.to_string(),
+    }
+}
+
+fn item_title(item: &Value, index: usize) -> String {
+    item.get("title")
+        .or_else(|| item.pointer("/chat/title"))
+        .and_then(Value::as_str)
+        .filter(|title| !title.trim().is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("导入的对话 {}", index + 1))
+}
+
+// 秒级时间戳（可能带小数）转换为毫秒
+fn seconds_to_millis(value: Option<&Value>) -> Option<u64> {
+    value
+        .and_then(Value::as_f64)
+        .filter(|seconds| *seconds > 0.0)
+        .map(|seconds| (seconds * 1000.0) as u64)
+}
+
+fn map_role(role: &str) -> Option<&'static str> {
+    match role {
+        "user" => Some("user"),
+        "assistant" => Some("bot"),
+        _ => None,
+    }
+}
+
+fn build_conversation(
+    title: String,
+    timestamp: u64,
+    messages: Vec<Message>,
+) -> ExportedConversation {
+    ExportedConversation {
+        conversation: Conversation {
+            id: 0,
+            title,
+            last_message: messages
+                .last()
+                .map(|m| m.content.clone())
+                .unwrap_or_default(),
+            timestamp,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+        },
+        settings: None,
+        messages,
+    }
+}
+
+fn new_message(content: String, sender: &str, timestamp: u64) -> Message {
+    Message {
+        id: 0,
+        content,
+        sender: sender.to_string(),
+        timestamp,
+        conversation_id: 0,
+        partial: false,
+        parent_id: None,
+    }
+}
+
+// 沿 parent 链从叶子节点走到根节点，返回从根到叶子的节点 id
+fn walk_to_root<'a>(leaf: &'a str, parent_of: impl Fn(&'a str) -> Option<&'a str>) -> Vec<&'a str> {
+    let mut path = Vec::new();
+    let mut visited = HashSet::new();
+    let mut current = Some(leaf);
+    while let Some(id) = current {
+        if !visited.insert(id) {
+            break;
+        }
+        path.push(id);
+        current = parent_of(id);
+    }
+    path.reverse();
+    path
+}
+
+/// ChatGPT 数据导出中 conversations.json 的单个对话
+///
+/// 消息以树的形式保存在 mapping 中（编辑和重新生成会产生分支），
+/// 只导入 current_node 所在的分支
+fn parse_chatgpt_conversation(
+    item: &Value,
+    index: usize,
+    skipped_items: &mut Vec<SkippedItem>,
+) -> Result<ExportedConversation, String> {
+    let title = item_title(item, index);
+    let mapping = item
+        .get("mapping")
+        .and_then(Value::as_object)
+        .ok_or("缺少 mapping 字段")?;
+    let created = seconds_to_millis(item.get("create_time"))
+        .unwrap_or_else(|| Utc::now().timestamp_millis() as u64);
+
+    let parent_of = |id: &str| {
+        mapping
+            .get(id)
+            .and_then(|node| node.get("parent"))
+            .and_then(Value::as_str)
+    };
+    // 没有 current_node 时从根节点沿最后一个子节点走到叶子
+    let leaf = match item.get("current_node").and_then(Value::as_str) {
+        Some(id) if mapping.contains_key(id) => id.to_string(),
+        _ => {
+            let mut id = mapping
+                .iter()
+                .find(|(_, node)| node.get("parent").is_none_or(Value::is_null))
+                .map(|(id, _)| id.clone())
+                .ok_or("找不到根节点")?;
+            let mut visited = HashSet::new();
+            while visited.insert(id.clone()) {
+                match mapping[&id]
+                    .get("children")
+                    .and_then(Value::as_array)
+                    .and_then(|children| children.last())
+                    .and_then(Value::as_str)
+                {
+                    Some(child) if mapping.contains_key(child) => id = child.to_string(),
+                    _ => break,
+                }
+            }
+            id
+        }
+    };
+
+    let mut messages = Vec::new();
+    for id in walk_to_root(&leaf, parent_of) {
+        let Some(message) = mapping[id].get("message").filter(|m| !m.is_null()) else {
+            continue;
+        };
+        let hidden = message
+            .pointer("/metadata/is_visually_hidden_from_conversation")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if hidden {
+            continue;
+        }
+
+        let role = message
+            .pointer("/author/role")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let content = message.get("content");
+        let content_type = content
+            .and_then(|c| c.get("content_type"))
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let text = match content_type {
+            "text" | "multimodal_text" => content
+                .and_then(|c| c.get("parts"))
+                .and_then(Value::as_array)
+                .map(|parts| {
+                    parts
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .unwrap_or_default(),
+            _ => content
+                .and_then(|c| c.get("text"))
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string(),
+        };
+        if text.trim().is_empty() {
+            if map_role(role).is_some() && content_type != "text" {
+                skipped_items.push(skipped(
+                    &title,
+                    Some(id),
+                    &format!("不支持的内容类型 {}", content_type),
+                ));
+            }
+            continue;
+        }
+        let Some(sender) = map_role(role) else {
+            skipped_items.push(skipped(&title, Some(id), &format!("不支持的角色 {}", role)));
+            continue;
+        };
+
+        let timestamp = seconds_to_millis(message.get("create_time")).unwrap_or(created);
+        messages.push(new_message(text, sender, timestamp));
+    }
+
+    Ok(build_conversation(title, created, messages))
+}
+
+/// Open WebUI 导出的单个对话
+///
+/// 新版本在 chat.history 中以树保存消息，沿 currentId 导入当前分支；
+/// 旧版本只有 chat.messages 数组
+fn parse_open_webui_chat(
+    item: &Value,
+    index: usize,
+    skipped_items: &mut Vec<SkippedItem>,
+) -> Result<ExportedConversation, String> {
+    let title = item_title(item, index);
+    let chat = item.get("chat").ok_or("缺少 chat 字段")?;
+    let created = seconds_to_millis(item.get("created_at"))
+        .or_else(|| chat.get("timestamp").and_then(Value::as_u64))
+        .unwrap_or_else(|| Utc::now().timestamp_millis() as u64);
+
+    let history = chat.pointer("/history/messages").and_then(Value::as_object);
+    let current = chat.pointer("/history/currentId").and_then(Value::as_str);
+    let nodes: Vec<&Value> = match (history, current) {
+        (Some(history), Some(current)) if history.contains_key(current) => {
+            let parent_of = |id: &str| {
+                history
+                    .get(id)
+                    .and_then(|node| node.get("parentId"))
+                    .and_then(Value::as_str)
+            };
+            walk_to_root(current, parent_of)
+                .into_iter()
+                .filter_map(|id| history.get(id))
+                .collect()
+        }
+        _ => chat
+            .get("messages")
+            .and_then(Value::as_array)
+            .ok_or("缺少消息记录")?
+            .iter()
+            .collect(),
+    };
+
+    let mut messages = Vec::new();
+    for node in nodes {
+        let id = node.get("id").and_then(Value::as_str);
+        let role = node.get("role").and_then(Value::as_str).unwrap_or("");
+        let content = node.get("content").and_then(Value::as_str).unwrap_or("");
+        if content.trim().is_empty() {
+            continue;
+        }
+        let Some(sender) = map_role(role) else {
+            skipped_items.push(skipped(&title, id, &format!("不支持的角色 {}", role)));
+            continue;
+        };
+        let timestamp = seconds_to_millis(node.get("timestamp")).unwrap_or(created);
+        messages.push(new_message(content.to_string(), sender, timestamp));
+    }
+
+    Ok(build_conversation(title, created, messages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_current_branch_of_chatgpt_export() {
+        let json = r#"[{
+            "title": "旅行计划",
+            "create_time": 1700000000.5,
+            "current_node": "c",
+            "mapping": {
+                "root": {"id": "root", "message": null, "parent": null, "children": ["s"]},
+                "s": {"id": "s", "parent": "root", "children": ["a"], "message": {
+                    "author": {"role": "system"}, "create_time": null,
+                    "content": {"content_type": "text", "parts": [""]},
+                    "metadata": {"is_visually_hidden_from_conversation": true}}},
+                "a": {"id": "a", "parent": "s", "children": ["b", "old"], "message": {
+                    "author": {"role": "user"}, "create_time": 1700000001.0,
+                    "content": {"content_type": "text", "parts": ["去哪里玩？"]}}},
+                "old": {"id": "old", "parent": "a", "children": [], "message": {
+                    "author": {"role": "assistant"}, "create_time": 1700000002.0,
+                    "content": {"content_type": "text", "parts": ["旧的回答"]}}},
+                "b": {"id": "b", "parent": "a", "children": ["c"], "message": {
+                    "author": {"role": "tool"}, "create_time": 1700000003.0,
+                    "content": {"content_type": "text", "parts": ["搜索结果"]}}},
+                "c": {"id": "c", "parent": "b", "children": [], "message": {
+                    "author": {"role": "assistant"}, "create_time": 1700000004.0,
+                    "content": {"content_type": "text", "parts": ["去杭州"]}}}
+            }
+        }]"#;
+
+        let parsed = parse_import(json).unwrap();
+        assert_eq!(parsed.source, "chatgpt");
+        let conversation = &parsed.conversations[0];
+        assert_eq!(conversation.conversation.title, "旅行计划");
+        let contents: Vec<_> = conversation
+            .messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["去哪里玩？", "去杭州"]);
+        assert_eq!(conversation.messages[1].sender, "bot");
+        assert_eq!(conversation.messages[0].timestamp, 1_700_000_001_000);
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].message.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn imports_open_webui_history() {
+        let json = r#"[{
+            "id": "1", "title": "问候", "created_at": 1700000000,
+            "chat": {
+                "title": "问候",
+                "history": {
+                    "currentId": "m2",
+                    "messages": {
+                        "m1": {"id": "m1", "parentId": null, "role": "user", "content": "你好", "timestamp": 1700000001},
+                        "m2": {"id": "m2", "parentId": "m1", "role": "assistant", "content": "你好！", "timestamp": 1700000002}
+                    }
+                }
+            }
+        }, {
+            "id": "2", "title": "空对话", "created_at": 1700000000,
+            "chat": {"messages": []}
+        }]"#;
+
+        let parsed = parse_import(json).unwrap();
+        assert_eq!(parsed.source, "open_webui");
+        assert_eq!(parsed.conversations.len(), 1);
+        assert_eq!(parsed.conversations[0].messages.len(), 2);
+        assert_eq!(parsed.conversations[0].conversation.last_message, "你好！");
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].conversation, "空对话");
+    }
+
+    #[test]
+    fn untitled_conversations_get_distinct_titles() {
+        let json = r#"[
+            {"chat": {"messages": [{"role": "user", "content": "第一个"}]}},
+            {"title": "", "chat": {"messages": [{"role": "user", "content": "第二个"}]}}
+        ]"#;
+
+        let parsed = parse_import(json).unwrap();
+        let titles: Vec<_> = parsed
+            .conversations
+            .iter()
+            .map(|c| c.conversation.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["导入的对话 1", "导入的对话 2"]);
+    }
+
+    #[test]
+    fn pinned_archived_and_tags_round_trip() {
+        use crate::services::database::ChatDatabase;
+        use crate::services::export::{render, ExportFormat};
+
+        let exported = ExportedConversation {
+            conversation: Conversation {
+                id: 7,
+                title: "周报".to_string(),
+                last_message: "本周进展".to_string(),
+                timestamp: 1,
+                pinned: true,
+                archived: true,
+                tags: vec!["工作".to_string(), "周报".to_string()],
+            },
+            settings: None,
+            messages: vec![new_message("本周进展".to_string(), "user", 1)],
+        };
+        let json = render(ExportFormat::Json, &[exported]).unwrap();
+        let parsed = parse_import(&json).unwrap();
+
+        let mut db = ChatDatabase::new(":memory:").unwrap();
+        let imported = &parsed.conversations[0];
+        let id = db
+            .import_conversation(&imported.conversation, &imported.messages, None)
+            .unwrap();
+        let conversation = db.get_conversation(id).unwrap().unwrap();
+        assert!(conversation.pinned);
+        assert!(conversation.archived);
+        let mut tags = conversation.tags;
+        tags.sort();
+        assert_eq!(tags, vec!["周报", "工作"]);
+    }
+}
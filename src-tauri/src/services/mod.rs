@@ -1,6 +1,9 @@
 // 添加agent中功能
 pub mod agent;
-// pub mod tts;
 pub mod asr;
+pub mod tts;
 // pub mod config;
 pub mod database;
+pub mod export;
+pub mod import;
+pub mod title;
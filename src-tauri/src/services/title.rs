@@ -0,0 +1,97 @@
+use crate::models::Message;
+use crate::services::agent::{AgentError, ChatBackend, ChatOptions};
+use tokio_stream::StreamExt;
+
+// 标题的最大字符数，超出部分截断
+const MAX_TITLE_CHARS: usize = 30;
+// 发给模型的对话内容上限，避免很长的首轮回复占满上下文
+const MAX_EXCERPT_CHARS: usize = 1000;
+
+const TITLE_PROMPT: &str = "你负责为对话起标题。根据用户提供的对话内容，\
+用对话所用的语言写一个不超过15个字的简短标题。只输出标题本身，不要加引号、标点或任何解释。";
+
+/// 根据第一轮问答生成对话标题，`model` 为空时使用后端当前的模型
+///
+/// 模型没有给出可用的标题时返回 `Ok(None)`
+pub async fn generate_title(
+    agent: &dyn ChatBackend,
+    model: &str,
+    question: &str,
+    answer: &str,
+) -> Result<Option<String>, AgentError> {
+    let prompt = Message {
+        id: 0,
+        content: format!("用户：{}\n\n助手：{}", excerpt(question), excerpt(answer)),
+        sender: "user".to_string(),
+        timestamp: 0,
+        conversation_id: 0,
+        partial: false,
+        parent_id: None,
+    };
+    let options = ChatOptions {
+        model: Some(model.to_string()).filter(|m| !m.trim().is_empty()),
+        system_prompt: Some(TITLE_PROMPT.to_string()),
+        temperature: Some(0.3),
+        ..Default::default()
+    };
+
+    let mut stream = agent
+        .chat_stream(std::slice::from_ref(&prompt), &options)
+        .await?;
+    let mut raw = String::new();
+    while let Some(chunk) = stream.next().await {
+        raw.push_str(&chunk?);
+    }
+    Ok(clean_title(&raw))
+}
+
+fn excerpt(text: &str) -> String {
+    text.chars().take(MAX_EXCERPT_CHARS).collect()
+}
+
+/// 整理模型输出：去掉思考过程、前缀、引号和结尾标点，只保留第一行
+pub fn clean_title(raw: &str) -> Option<String> {
+    // 推理模型会先输出 <think>...</think>
+    let text = match raw.rfind("</think>") {
+        Some(end) => &raw[end + "</think>".len()..],
+        None => raw,
+    };
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = ["标题：", "标题:", "Title:", "title:"]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+        .unwrap_or(line);
+
+    let quotes: &[char] = &[
+        '"', '\'', '“', '”', '‘', '’', '《', '》', '「', '」', '*', '#', '`',
+    ];
+    let punctuation: &[char] = &['。', '.', '！', '!', '？', '?', '，', ',', '：', ':'];
+    let title = line
+        .trim_start_matches(|c: char| c.is_whitespace() || quotes.contains(&c))
+        .trim_end_matches(|c: char| {
+            c.is_whitespace() || quotes.contains(&c) || punctuation.contains(&c)
+        });
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(MAX_TITLE_CHARS).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_model_output() {
+        assert_eq!(
+            clean_title("<think>用户在问天气</think>\n\n标题：“北京天气查询”。\n解释……"),
+            Some("北京天气查询".to_string())
+        );
+        assert_eq!(
+            clean_title("**Rust ownership basics**"),
+            Some("Rust ownership basics".to_string())
+        );
+        assert_eq!(clean_title("  \n「」\n"), None);
+        assert_eq!(clean_title(&"长".repeat(50)).unwrap().chars().count(), 30);
+    }
+}
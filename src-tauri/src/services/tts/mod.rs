@@ -1,3 +1,5 @@
+#[cfg(feature = "python-tts")]
 pub mod natural_tts;
+pub mod speaker;
 // pub mod kokoro_tts;
-// pub mod kokoro;
\ No newline at end of file
+// pub mod kokoro;
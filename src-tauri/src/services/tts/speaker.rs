@@ -0,0 +1,211 @@
+#[cfg(feature = "python-tts")]
+use crate::services::tts::natural_tts::TTSHandler;
+use log::debug;
+use rodio::{Decoder, OutputStream, Sink};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "python-tts")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// 播放线程检查停止请求的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[cfg(feature = "python-tts")]
+static NEXT_CLIP_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 用 edge-tts 把文本合成为语音文件，可以在多个任务中同时合成
+///
+/// 需要启用 python-tts 特性，否则创建时返回错误
+#[derive(Clone)]
+pub struct Speaker {
+    #[cfg(feature = "python-tts")]
+    handler: Arc<TTSHandler>,
+}
+
+#[cfg(feature = "python-tts")]
+impl Speaker {
+    /// 加载 Python 的语音合成模块
+    pub async fn new() -> Result<Self, String> {
+        let handler = tokio::task::spawn_blocking(TTSHandler::new)
+            .await
+            .map_err(|e| format!("语音合成初始化任务异常退出: {}", e))?
+            .map_err(|e| format!("初始化语音合成失败: {}", e))?;
+        Ok(Self {
+            handler: Arc::new(handler),
+        })
+    }
+
+    /// 合成一段文本，返回临时音频文件的路径，文件在播放结束后删除
+    pub async fn synthesize(&self, text: &str) -> Result<PathBuf, String> {
+        let path = std::env::temp_dir().join(format!(
+            "chat_box_tts_{}_{}.mp3",
+            std::process::id(),
+            NEXT_CLIP_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        let handler = self.handler.clone();
+        let text = text.to_string();
+        let output = path.clone();
+        tokio::task::spawn_blocking(move || handler.convert(&text, &output.to_string_lossy()))
+            .await
+            .map_err(|e| format!("语音合成任务异常退出: {}", e))?
+            .map_err(|e| format!("语音合成失败: {}", e))?;
+        Ok(path)
+    }
+}
+
+#[cfg(not(feature = "python-tts"))]
+impl Speaker {
+    pub async fn new() -> Result<Self, String> {
+        Err("构建时没有启用 python-tts 特性，无法朗读回复".to_string())
+    }
+
+    pub async fn synthesize(&self, _text: &str) -> Result<PathBuf, String> {
+        Err("构建时没有启用 python-tts 特性，无法朗读回复".to_string())
+    }
+}
+
+/// 正在播放的语音，释放时停止播放
+pub struct Playback {
+    stop: Arc<AtomicBool>,
+    done: oneshot::Receiver<Result<(), String>>,
+}
+
+impl Playback {
+    /// 在专用线程中播放音频文件，播放结束或停止后删除文件
+    pub fn start(path: PathBuf) -> Result<Self, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (done_tx, done) = oneshot::channel();
+        let thread_stop = stop.clone();
+
+        // rodio 的输出流不能跨线程移动
+        thread::Builder::new()
+            .name("tts-playback".to_string())
+            .spawn(move || {
+                let result = play(&path, &thread_stop);
+                if let Err(e) = std::fs::remove_file(&path) {
+                    debug!("删除临时音频文件 {:?} 失败: {}", path, e);
+                }
+                let _ = done_tx.send(result);
+            })
+            .map_err(|e| format!("启动播放线程失败: {}", e))?;
+
+        Ok(Self { stop, done })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    /// 等待播放结束，被停止时也会返回
+    pub async fn finished(&mut self) -> Result<(), String> {
+        (&mut self.done)
+            .await
+            .map_err(|_| "播放线程意外退出".to_string())?
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn play(path: &Path, stop: &AtomicBool) -> Result<(), String> {
+    let (_stream, handle) =
+        OutputStream::try_default().map_err(|e| format!("打开音频输出设备失败: {}", e))?;
+    let sink = Sink::try_new(&handle).map_err(|e| format!("创建播放器失败: {}", e))?;
+    let file = File::open(path).map_err(|e| format!("打开音频文件失败: {}", e))?;
+    let source =
+        Decoder::new(BufReader::new(file)).map_err(|e| format!("无法解码音频文件: {}", e))?;
+    sink.append(source);
+
+    while !sink.empty() {
+        if stop.load(Ordering::SeqCst) {
+            debug!("停止播放");
+            sink.stop();
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// 把模型的回复整理成适合朗读的句子
+///
+/// 去掉推理过程、代码块和 Markdown 标记，按句末标点和换行分句，
+/// 这样第一句合成好就能开始播放
+pub fn speech_sentences(reply: &str) -> Vec<String> {
+    // 推理模型会先输出 <think>...</think>
+    let text = match reply.rfind("</think>") {
+        Some(end) => &reply[end + "</think>".len()..],
+        None => reply,
+    };
+
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut in_code_block = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if matches!(c, '*' | '#' | '`') {
+                continue;
+            }
+            current.push(c);
+            // 英文句号后面要有空白，避免拆开小数和缩写
+            let end_of_sentence = match c {
+                '。' | '！' | '？' | '；' | '!' | '?' | ';' => true,
+                '.' => chars.peek().is_none_or(|next| next.is_whitespace()),
+                _ => false,
+            };
+            if end_of_sentence {
+                push_sentence(&mut sentences, &mut current);
+            }
+        }
+        push_sentence(&mut sentences, &mut current);
+    }
+    sentences
+}
+
+// 只有标点或空白的片段不朗读
+fn push_sentence(sentences: &mut Vec<String>, current: &mut String) {
+    let sentence = current.trim();
+    if sentence.chars().any(char::is_alphanumeric) {
+        sentences.push(sentence.to_string());
+    }
+    current.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_reply_into_readable_sentences() {
+        let reply = "<think>先想一想</think>\n## 答案\n圆周率约为 3.14。**记住**它！\n\n```rust\nlet x = 1;\n```\n- 第一点\nDone. OK";
+        assert_eq!(
+            speech_sentences(reply),
+            vec![
+                "答案",
+                "圆周率约为 3.14。",
+                "记住它！",
+                "- 第一点",
+                "Done.",
+                "OK"
+            ]
+        );
+    }
+}
@@ -1,45 +1,99 @@
-use crate::models::{Conversation, Message};
-use crate::services::agent::ollama::OllamaAgent;
-use crate::services::asr::vosk_python::VoskASR;
-use crate::services::database::ChatDatabase;
+use crate::services::agent::ChatBackend;
+use crate::services::asr::VoskASR;
+use crate::services::database::{ChatRepository, DatabasePool};
 use crate::utils::config::AppConfig;
 use log::{error, info};
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::oneshot;
+
+/// 正在进行的AI生成任务
+pub struct GenerationHandle {
+    /// 正在写入的机器人消息ID
+    pub message_id: u64,
+    /// 发送后生成任务会停止读取回复流
+    pub cancel: oneshot::Sender<()>,
+}
+
+/// 发给语音输入任务的指令
+pub enum VoiceControl {
+    /// 停止录音，通过通道返回最终识别的文本
+    Stop(oneshot::Sender<Result<String, String>>),
+    /// 停止录音并丢弃识别结果
+    Cancel,
+}
+
+/// 正在进行的语音输入
+pub struct VoiceSession {
+    pub id: u64,
+    pub conversation_id: u64,
+    pub control: oneshot::Sender<VoiceControl>,
+}
+
+/// 正在进行的对话模式
+pub struct TalkSession {
+    pub id: u64,
+    pub conversation_id: u64,
+    /// 发送后对话模式停止录音和朗读并退出
+    pub stop: oneshot::Sender<()>,
+}
 
 pub struct AppState {
     pub config: Arc<Mutex<AppConfig>>,
-    pub conversations: Arc<Mutex<Vec<Conversation>>>,
-    pub messages: Arc<Mutex<Vec<Message>>>,
-    pub ollama_agent: Arc<OllamaAgent>,
+    pub agent: Arc<RwLock<Arc<dyn ChatBackend>>>, // 配置变更时整体替换
     pub vosk_asr: Arc<tokio::sync::Mutex<VoskASR>>,
-    pub db: Arc<Mutex<Option<ChatDatabase>>>, // 添加数据库支持
+    pub db: Arc<RwLock<Option<DatabasePool>>>, // 添加数据库支持
+    pub repository: ChatRepository,            // 对话和消息只保存在数据库中
+    pub generations: Arc<Mutex<HashMap<u64, GenerationHandle>>>, // 按对话ID记录进行中的生成
+    pub voice_session: Arc<Mutex<Option<VoiceSession>>>, // 麦克风同一时间只有一个录音
+    pub talk_session: Arc<Mutex<Option<TalkSession>>>, // 同一时间只有一个对话模式
+    pub database_error: Arc<Mutex<Option<String>>>, // 数据库打开失败的原因
 }
 
 #[allow(dead_code)]
 impl AppState {
-    pub fn new(
-        config: AppConfig,
-        conversations: Vec<Conversation>,
-        messages: Vec<Message>,
-        ollama_agent: OllamaAgent,
-        vosk_asr: VoskASR,
-    ) -> Self {
+    pub fn new(config: AppConfig, agent: Arc<dyn ChatBackend>, vosk_asr: VoskASR) -> Self {
+        let db = Arc::new(RwLock::new(None)); // 初始时数据库为None
         AppState {
             config: Arc::new(Mutex::new(config)),
-            conversations: Arc::new(Mutex::new(conversations)),
-            messages: Arc::new(Mutex::new(messages)),
-            ollama_agent: Arc::new(ollama_agent),
+            agent: Arc::new(RwLock::new(agent)),
             vosk_asr: Arc::new(tokio::sync::Mutex::new(vosk_asr)),
-            db: Arc::new(Mutex::new(None)), // 初始时数据库为None
+            repository: ChatRepository::new(db.clone()),
+            db,
+            generations: Arc::new(Mutex::new(HashMap::new())),
+            voice_session: Arc::new(Mutex::new(None)),
+            talk_session: Arc::new(Mutex::new(None)),
+            database_error: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // 获取当前使用的模型后端，进行中的请求会继续使用旧的实例
+    pub fn agent(&self) -> Arc<dyn ChatBackend> {
+        self.agent.read().unwrap().clone()
+    }
+
+    // 替换模型后端
+    pub fn set_agent(&self, agent: Arc<dyn ChatBackend>) {
+        *self.agent.write().unwrap() = agent;
+    }
+
+    // 替换数据库连接池，进行中的操作会在旧的连接上完成
+    pub fn replace_database(&self, db: Option<DatabasePool>) {
+        if db.is_some() {
+            self.set_database_error(None);
         }
+        *self.db.write().unwrap() = db;
+    }
+
+    pub fn set_database_error(&self, error: Option<String>) {
+        *self.database_error.lock().unwrap() = error;
     }
 
     // 初始化数据库
     pub fn init_database(&self, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        match ChatDatabase::new(db_path) {
+        match DatabasePool::open(db_path) {
             Ok(db) => {
-                let mut db_guard = self.db.lock().unwrap();
-                *db_guard = Some(db);
+                self.replace_database(Some(db));
                 info!("Database initialized at: {}", db_path);
                 Ok(())
             }
@@ -49,70 +103,4 @@ impl AppState {
             }
         }
     }
-
-    // 从数据库加载所有对话和消息
-    pub fn load_from_database(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let db_guard = self.db.lock().unwrap();
-        if let Some(ref db) = *db_guard {
-            // 加载所有对话
-            let conversations = db.get_all_conversations()?;
-            {
-                let mut conv_guard = self.conversations.lock().unwrap();
-                *conv_guard = conversations.clone();
-            }
-
-            // 加载所有对话的消息
-            let mut all_messages = Vec::new();
-            for conv in &conversations {
-                let msgs = db.get_conversation_messages(conv.id)?;
-                all_messages.extend(msgs);
-            }
-
-            {
-                let mut msg_guard = self.messages.lock().unwrap();
-                *msg_guard = all_messages;
-            }
-
-            info!("加载了{}个对话和相关消息", conversations.len());
-            Ok(())
-        } else {
-            Err("Database not initialized".into())
-        }
-    }
-
-    // 保存所有对话和消息到数据库
-    pub fn save_to_database(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut db_guard = self.db.lock().unwrap();
-        if let Some(ref mut db) = *db_guard {
-            // 保存所有对话
-            {
-                let conv_guard = self.conversations.lock().unwrap();
-                for conv in conv_guard.iter() {
-                    db.save_conversation(conv)?;
-                }
-                info!("保存了{}个对话", conv_guard.len());
-            }
-
-            // 保存所有消息
-            {
-                let msg_guard = self.messages.lock().unwrap();
-                db.save_messages(&msg_guard)?;
-                info!("保存了{}条消息", msg_guard.len());
-            }
-
-            Ok(())
-        } else {
-            Err("Database not initialized".into())
-        }
-    }
-
-    // 获取特定对话的历史记录
-    pub fn get_conversation_history(&self, conversation_id: u64) -> Vec<Message> {
-        let msg_guard = self.messages.lock().unwrap();
-        msg_guard
-            .iter()
-            .filter(|msg| msg.conversation_id == conversation_id)
-            .cloned()
-            .collect()
-    }
 }
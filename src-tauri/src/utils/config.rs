@@ -1,49 +1,117 @@
-use log::{error, info};
+use log::{error, info, warn, LevelFilter};
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use std::fs;
-use std::path::PathBuf;
-use tauri::State;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, Emitter, Manager, State, Window};
 
+use crate::services::agent::{create_backend, ChatBackend};
+use crate::services::asr::{create_vosk_asr, VoskASR};
+use crate::services::database::DatabasePool;
 use crate::state::AppState;
+use crate::utils::logger::apply_log_level;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// 当前配置文件结构的版本，结构变化时递增并在 migrate_config 中添加迁移
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DatabaseConfig {
     pub enabled: bool,
     pub path: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AiModelConfig {
+    /// 模型服务提供方: "ollama" 或 "openai"（OpenAI 兼容接口）
+    #[serde(default = "default_provider")]
+    pub provider: String,
     pub model_name: String,
     pub server_url: String,
     pub server_port: u16,
     pub system_prompt: String,
+    /// OpenAI 兼容服务的 API Key，本地服务通常留空
+    #[serde(default)]
+    pub api_key: String,
+}
+
+fn default_provider() -> String {
+    "ollama".to_string()
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct VoiceConfig {
     pub enabled: bool,
     pub model_path: String,
     pub timeout_seconds: u64,
+    /// 录音使用的麦克风名称，为空时使用系统默认设备
+    #[serde(default)]
+    pub input_device: String,
+    /// 检测开始说话和一句话结束的参数
+    #[serde(default)]
+    pub vad: VadConfig,
+    /// 对话模式中播放回复时，用户开始说话就停止播放，使用外放音箱时容易被回声打断，默认关闭
+    #[serde(default)]
+    pub barge_in: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VadConfig {
+    /// 音量高出环境噪声多少分贝算作语音
+    pub threshold_db: f32,
+    /// 语音持续多久才算开始说话，用于过滤咳嗽、敲击等短促的声音
+    pub min_speech_ms: u64,
+    /// 停止说话多久后算作一句话结束
+    pub hangover_ms: u64,
+    /// 开始说话前保留的音频长度，避免丢掉第一个字
+    pub pre_roll_ms: u64,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            threshold_db: 10.0,
+            min_speech_ms: 100,
+            hangover_ms: 1500,
+            pre_roll_ms: 300,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct UiConfig {
     pub theme: String,
     pub language: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AppBehaviorConfig {
     pub log_level: String,
     pub default_conversation_title: String,
     pub welcome_message: String,
     pub message_chunk_buffer_size: usize,
     pub message_chunk_send_interval_ms: u64,
+    /// 第一轮对话完成后自动生成对话标题
+    #[serde(default = "default_auto_title")]
+    pub auto_title: bool,
+    /// 生成标题使用的模型，为空时使用当前对话的模型
+    #[serde(default)]
+    pub title_model: String,
+}
+
+fn default_auto_title() -> bool {
+    true
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AppConfig {
+    /// 配置文件结构版本，用于迁移旧的配置文件
+    #[serde(default)]
+    pub version: u32,
+    /// 用户配置文件的位置，运行时确定，不写入文件
+    #[serde(skip)]
     pub config_path: PathBuf,
     pub ai_model: AiModelConfig,
     pub voice: VoiceConfig,
@@ -55,17 +123,23 @@ pub struct AppConfig {
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
-            config_path: "config.yaml".into(),
+            version: CONFIG_VERSION,
+            config_path: PathBuf::new(),
             ai_model: AiModelConfig {
+                provider: default_provider(),
                 model_name: "qwen2.5:0.5b".to_string(),
                 server_url: "http://localhost".to_string(),
                 server_port: 11434,
                 system_prompt: "你是一个友好、乐于助人的AI助手，使用中文回答问题。".to_string(),
+                api_key: String::new(),
             },
             voice: VoiceConfig {
                 enabled: false,
                 model_path: "model/vosk-model-small-cn-0.22".to_string(),
                 timeout_seconds: 15,
+                input_device: String::new(),
+                vad: VadConfig::default(),
+                barge_in: false,
             },
             ui: UiConfig {
                 theme: "light".to_string(),
@@ -77,6 +151,8 @@ impl Default for AppConfig {
                 welcome_message: "欢迎使用聊天应用!".to_string(),
                 message_chunk_buffer_size: 2,
                 message_chunk_send_interval_ms: 3,
+                auto_title: default_auto_title(),
+                title_model: String::new(),
             },
             database: DatabaseConfig {
                 enabled: true,
@@ -94,69 +170,194 @@ impl AppConfig {
         }
     }
 
-    pub fn load_config(self) -> AppConfig {
-        // 尝试从配置文件加载配置
-        match self.clone().get_config_file_path() {
-            Some(config_path) => {
-                if config_path.exists() {
-                    match fs::read_to_string(&config_path) {
-                        Ok(yaml_str) => match serde_yaml::from_str(&yaml_str) {
-                            Ok(config) => {
-                                info!("配置已从 {:?} 加载", config_path);
-                                return config;
-                            }
-                            Err(e) => {
-                                error!("解析配置文件失败: {}", e);
-                            }
-                        },
-                        Err(e) => {
-                            error!("读取配置文件失败: {}", e);
-                        }
-                    }
-                }
-
-                // 文件不存在，创建默认配置文件
-                let default_config = AppConfig::default();
-                self.save_config(&default_config, &config_path);
-                default_config
-            }
+    /// 加载用户配置，`bundled_path` 为安装包自带的默认配置
+    ///
+    /// 用户配置不存在时以自带配置为初始内容写入用户目录；
+    /// 文件中缺少的字段使用默认值补齐，旧版本的配置会先迁移到当前版本
+    pub fn load_config(self, bundled_path: Option<&Path>) -> AppConfig {
+        let config_path = match self.clone().get_config_file_path() {
+            Some(config_path) => config_path,
             None => {
                 error!("无法确定配置文件路径");
-                AppConfig::default()
+                return AppConfig::default();
+            }
+        };
+
+        let source = if config_path.exists() {
+            Some(config_path.as_path())
+        } else {
+            bundled_path.filter(|path| path.exists())
+        };
+        let (mut config, needs_save) = match source {
+            Some(source) => match Self::read_config_file(source) {
+                Ok((config, migrated)) => {
+                    info!("配置已从 {:?} 加载", source);
+                    (config, migrated || source != config_path)
+                }
+                Err(e) => {
+                    // 不覆盖用户的配置文件，方便手动修复
+                    error!("加载配置文件 {:?} 失败，使用默认配置: {}", source, e);
+                    (AppConfig::default(), source != config_path)
+                }
+            },
+            None => (AppConfig::default(), true),
+        };
+        config.config_path = config_path.clone();
+        if needs_save {
+            if let Err(e) = self.save_config(&config, &config_path) {
+                error!("{}", e);
             }
         }
+        config
+    }
+
+    /// 读取配置文件，返回配置以及是否进行了版本迁移
+    fn read_config_file(path: &Path) -> Result<(AppConfig, bool), String> {
+        let yaml_str = fs::read_to_string(path).map_err(|e| format!("读取配置文件失败: {}", e))?;
+        let value: Value =
+            serde_yaml::from_str(&yaml_str).map_err(|e| format!("解析配置文件失败: {}", e))?;
+        Self::from_yaml_value(value)
     }
 
-    pub fn save_config(&self, config: &AppConfig, path: &PathBuf) {
+    /// 迁移并合并到默认配置上，使缺少字段的文件也能正常加载
+    fn from_yaml_value(value: Value) -> Result<(AppConfig, bool), String> {
+        let (value, migrated) = migrate_config(value)?;
+        let mut merged = serde_yaml::to_value(AppConfig::default())
+            .map_err(|e| format!("序列化默认配置失败: {}", e))?;
+        merge_yaml(&mut merged, value);
+        let config =
+            serde_yaml::from_value(merged).map_err(|e| format!("解析配置文件失败: {}", e))?;
+        Ok((config, migrated))
+    }
+
+    pub fn save_config(&self, config: &AppConfig, path: &PathBuf) -> Result<(), String> {
         // 确保目录存在
         if let Some(parent) = path.parent() {
             if !parent.exists() {
-                if let Err(e) = fs::create_dir_all(parent) {
-                    error!("创建配置目录失败: {}", e);
-                    return;
-                }
+                fs::create_dir_all(parent).map_err(|e| format!("创建配置目录失败: {}", e))?;
             }
         }
 
         // 写入配置文件
-        match serde_yaml::to_string(config) {
-            Ok(yaml_str) => match fs::write(path, yaml_str) {
-                Ok(_) => {
-                    info!("配置已保存到 {:?}", path);
-                }
-                Err(e) => {
-                    error!("写入配置文件失败: {}", e);
-                }
-            },
-            Err(e) => {
-                error!("序列化配置失败: {}", e);
-            }
+        let yaml_str =
+            serde_yaml::to_string(config).map_err(|e| format!("序列化配置失败: {}", e))?;
+        fs::write(path, yaml_str).map_err(|e| format!("写入配置文件失败: {}", e))?;
+        info!("配置已保存到 {:?}", path);
+        Ok(())
+    }
+
+    /// 检查配置是否可以应用到正在运行的服务
+    pub fn validate(&self) -> Result<(), String> {
+        let ai_model = &self.ai_model;
+        if !matches!(ai_model.provider.as_str(), "ollama" | "openai") {
+            return Err(format!("不支持的模型服务提供方: {}", ai_model.provider));
+        }
+        if ai_model.model_name.trim().is_empty() {
+            return Err("模型名称不能为空".to_string());
+        }
+        if !ai_model.server_url.starts_with("http://")
+            && !ai_model.server_url.starts_with("https://")
+        {
+            return Err(format!(
+                "服务地址必须以 http:// 或 https:// 开头: {}",
+                ai_model.server_url
+            ));
+        }
+        if ai_model.server_port == 0 {
+            return Err("服务端口不能为0".to_string());
         }
+        if self.voice.enabled && self.voice.model_path.trim().is_empty() {
+            return Err("启用语音时必须设置模型路径".to_string());
+        }
+        if self.voice.timeout_seconds == 0 {
+            return Err("语音超时时间必须大于0".to_string());
+        }
+        if self.voice.vad.threshold_db <= 0.0 {
+            return Err("语音检测阈值必须大于0".to_string());
+        }
+        if self.voice.vad.hangover_ms == 0 {
+            return Err("语音结束等待时间必须大于0".to_string());
+        }
+        if self.database.enabled && self.database.path.trim().is_empty() {
+            return Err("启用数据库时必须设置数据库路径".to_string());
+        }
+        if LevelFilter::from_str(&self.app_behavior.log_level).is_err() {
+            return Err(format!("无效的日志级别: {}", self.app_behavior.log_level));
+        }
+        if self.app_behavior.message_chunk_buffer_size == 0 {
+            return Err("消息缓冲大小必须大于0".to_string());
+        }
+        Ok(())
     }
 
     pub fn get_config_file_path(self) -> Option<PathBuf> {
-        let config_path = AppConfig::default().config_path.clone();
-        Some(config_path)
+        if self.config_path.as_os_str().is_empty() {
+            None
+        } else {
+            Some(self.config_path)
+        }
+    }
+}
+
+/// 将配置文件从旧版本逐步迁移到 CONFIG_VERSION，返回是否发生了迁移
+fn migrate_config(value: Value) -> Result<(Value, bool), String> {
+    let mut map = match value {
+        Value::Mapping(map) => map,
+        // 空文件按没有任何字段处理
+        Value::Null => Mapping::new(),
+        _ => return Err("配置文件的顶层必须是映射".to_string()),
+    };
+
+    let version = map.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > CONFIG_VERSION {
+        warn!(
+            "配置文件版本 {} 高于当前支持的版本 {}，未知字段将被忽略",
+            version, CONFIG_VERSION
+        );
+        return Ok((Value::Mapping(map), false));
+    }
+    if version == CONFIG_VERSION {
+        return Ok((Value::Mapping(map), false));
+    }
+
+    for from in version..CONFIG_VERSION {
+        match from {
+            0 => migrate_v0_to_v1(&mut map),
+            _ => unreachable!("缺少从版本 {} 开始的配置迁移", from),
+        }
+        info!("配置文件已从版本 {} 迁移到 {}", from, from + 1);
+    }
+    map.insert("version".into(), CONFIG_VERSION.into());
+    Ok((Value::Mapping(map), true))
+}
+
+/// v1: 配置文件路径不再写入文件；前端曾把 database.enabled 写成 database.enable
+fn migrate_v0_to_v1(map: &mut Mapping) {
+    map.remove("config_path");
+    if let Some(Value::Mapping(database)) = map.get_mut("database") {
+        if let Some(enable) = database.remove("enable") {
+            if !database.contains_key("enabled") {
+                database.insert("enabled".into(), enable);
+            }
+        }
+    }
+}
+
+/// 把 overlay 递归合并到 base 上，overlay 中的空值不覆盖 base
+fn merge_yaml(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (_, Value::Null) => {}
+        (base, overlay) => *base = overlay,
     }
 }
 
@@ -165,18 +366,182 @@ impl AppConfig {
 pub fn get_app_config(state: State<'_, AppState>) -> Result<AppConfig, String> {
     let binding = state.config.clone();
     let config = binding.lock().expect("获取配置失败");
-    Ok(config.clone().load_config())
+    Ok(config.clone())
+}
+
+/// 将相对路径解析到应用资源目录，绝对路径原样返回
+pub fn resolve_resource_path(handle: &AppHandle, path: &str) -> Result<String, String> {
+    if Path::new(path).is_absolute() {
+        return Ok(path.to_string());
+    }
+    handle
+        .path()
+        .resolve(path, BaseDirectory::Resource)
+        .map(|p| p.to_string_lossy().to_string())
+        .map_err(|e| e.to_string())
 }
 
+/// 按配置打开数据库，未启用时使用内存数据库
+pub fn open_database(handle: &AppHandle, config: &DatabaseConfig) -> Result<DatabasePool, String> {
+    if !config.enabled {
+        return DatabasePool::open_in_memory().map_err(|e| format!("数据库初始化失败: {}", e));
+    }
+    let db_path = resolve_resource_path(handle, &config.path)?;
+    if let Some(parent) = Path::new(&db_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("无法创建数据库目录: {}", e))?;
+    }
+    let db = DatabasePool::open(&db_path).map_err(|e| format!("数据库初始化失败: {}", e))?;
+    info!("Database initialized at: {}", db_path);
+    Ok(db)
+}
+
+/// 保存配置并应用到正在运行的服务
+///
+/// 先根据新配置创建所有需要替换的服务，再写入配置文件，任何一步失败都不会改动当前状态；
+/// 写入成功后再依次替换服务并通知前端
 #[tauri::command]
-pub fn save_app_config(state: State<'_, AppState>, save_config: AppConfig) -> Result<(), String> {
-    let binding = state.config.clone();
-    let config = binding.lock().expect("获取配置失败");
-    match config.clone().get_config_file_path() {
-        Some(path) => {
-            config.save_config(&save_config, &path);
-            Ok(())
+pub async fn save_app_config(
+    window: Window,
+    state: State<'_, AppState>,
+    config: AppConfig,
+) -> Result<(), String> {
+    let mut config = config;
+    config.validate()?;
+
+    let current = state.config.lock().expect("获取配置失败").clone();
+    config.config_path = current.config_path.clone();
+    config.version = CONFIG_VERSION;
+    let handle = window.app_handle();
+
+    let services = PreparedServices {
+        agent: if config.ai_model != current.ai_model {
+            Some(create_backend(&config.ai_model)?)
+        } else {
+            None
+        },
+        vosk_asr: if config.voice != current.voice {
+            Some(create_vosk_asr(handle, &config.voice)?)
+        } else {
+            None
+        },
+        database: if config.database != current.database {
+            Some(open_database(handle, &config.database)?)
+        } else {
+            None
+        },
+    };
+    commit_config(&state, &current, &config, services).await?;
+
+    if let Err(e) = window.emit("config_changed", &config) {
+        error!("发送配置变更事件失败: {}", e);
+    }
+    info!("新配置已生效");
+    Ok(())
+}
+
+/// 根据新配置创建好的服务，为 None 的服务不需要替换
+struct PreparedServices {
+    agent: Option<Arc<dyn ChatBackend>>,
+    vosk_asr: Option<VoskASR>,
+    database: Option<DatabasePool>,
+}
+
+// 写入配置文件后替换正在运行的服务，写入失败时直接返回，不改动当前状态
+async fn commit_config(
+    state: &AppState,
+    current: &AppConfig,
+    config: &AppConfig,
+    services: PreparedServices,
+) -> Result<(), String> {
+    let path = current
+        .clone()
+        .get_config_file_path()
+        .ok_or_else(|| "无法确定配置文件路径".to_string())?;
+    current.save_config(config, &path)?;
+
+    // 等待正在进行的录音结束后再替换
+    if let Some(vosk_asr) = services.vosk_asr {
+        *state.vosk_asr.lock().await = vosk_asr;
+        info!("语音识别服务已按新配置重建");
+    }
+    if let Some(agent) = services.agent {
+        info!("{} 后端已按新配置重建", agent.provider());
+        state.set_agent(agent);
+    }
+    if let Some(database) = services.database {
+        state.replace_database(Some(database));
+        if let Err(e) = state
+            .repository
+            .ensure_default_conversation(
+                config.app_behavior.default_conversation_title.clone(),
+                config.app_behavior.welcome_message.clone(),
+            )
+            .await
+        {
+            error!("创建默认对话失败: {}", e);
         }
-        None => Err("无法确定配置文件路径".to_string()),
+    }
+    if config.app_behavior.log_level != current.app_behavior.log_level {
+        apply_log_level(&config.app_behavior.log_level);
+    }
+
+    *state.config.lock().expect("获取配置失败") = config.clone();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_config_is_merged_over_defaults() {
+        let yaml = "version: 1\nai_model:\n  model_name: llama3\n";
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let (config, migrated) = AppConfig::from_yaml_value(value).unwrap();
+        assert!(!migrated);
+        assert_eq!(config.ai_model.model_name, "llama3");
+        assert_eq!(config.ai_model.server_port, 11434);
+        assert_eq!(config.voice, AppConfig::default().voice);
+    }
+
+    #[test]
+    fn unversioned_config_is_migrated() {
+        let yaml = "config_path: config.yaml\ndatabase:\n  enable: false\n  path: chat.db\n";
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let (config, migrated) = AppConfig::from_yaml_value(value).unwrap();
+        assert!(migrated);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(!config.database.enabled);
+        assert_eq!(config.database.path, "chat.db");
+    }
+
+    #[cfg(not(feature = "python-asr"))]
+    #[tokio::test]
+    async fn failed_save_leaves_state_unchanged() {
+        // 配置文件的上级路径是一个普通文件，写入一定失败
+        let blocker = std::env::temp_dir().join(format!("chat_box_config_{}", std::process::id()));
+        fs::write(&blocker, "").unwrap();
+        let current = AppConfig {
+            config_path: blocker.join("config.yaml"),
+            ..AppConfig::default()
+        };
+
+        let agent = create_backend(&current.ai_model).unwrap();
+        let state = AppState::new(current.clone(), agent, VoskASR::new(None).unwrap());
+
+        let mut config = current.clone();
+        config.ai_model.model_name = "another-model".to_string();
+        let services = PreparedServices {
+            agent: Some(create_backend(&config.ai_model).unwrap()),
+            vosk_asr: None,
+            database: Some(DatabasePool::open_in_memory().unwrap()),
+        };
+        let result = commit_config(&state, &current, &config, services).await;
+        fs::remove_file(&blocker).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(*state.config.lock().unwrap(), current);
+        assert_eq!(state.agent().model(), current.ai_model.model_name);
+        assert!(state.db.read().unwrap().is_none());
     }
 }
@@ -3,6 +3,7 @@ use env_logger::Env;
 use log::info;
 use log::LevelFilter;
 use std::io::Write;
+use std::str::FromStr;
 
 pub fn init_logger() {
     print_magic();
@@ -25,6 +26,17 @@ pub fn init_logger() {
     info!("env_logger initialized.");
 }
 
+/// 按配置调整日志级别，无法解析时保持不变
+pub fn apply_log_level(level: &str) {
+    match LevelFilter::from_str(level) {
+        Ok(filter) => {
+            log::set_max_level(filter);
+            info!("日志级别已设置为 {}", filter);
+        }
+        Err(_) => log::warn!("无效的日志级别: {}", level),
+    }
+}
+
 pub fn print_magic() {
     println!(
         r#"
@@ -4,6 +4,14 @@ export interface Message {
   content: string;
   sender: "user" | "bot";
   timestamp: number;
+  partial?: boolean;
+  parent_id?: number | null;
+}
+
+export interface MessageBranch {
+  message_id: number;
+  // 同一条上级消息下的所有版本，按创建顺序排列
+  siblings: number[];
 }
 
 export interface Conversation {
@@ -12,10 +20,100 @@ export interface Conversation {
   avatar: string;
   lastMessage: string;
   timestamp: number;
+  pinned?: boolean;
+  archived?: boolean;
+  tags?: string[];
+}
+
+export type ConversationSort = "recent" | "created" | "title";
+
+export interface ConversationFilter {
+  // 为 true 时只返回已归档的对话
+  archived?: boolean;
+  pinned_only?: boolean;
+  // 只返回带有全部这些标签的对话
+  tags?: string[];
+  sort?: ConversationSort;
 }
 
 export interface MessageChunk {
   conversation_id: number;
   content: string;
   is_complete: boolean;
+  cancelled?: boolean;
+}
+
+export interface MessageError {
+  conversation_id: number;
+  message_id: number | null;
+  kind: "connection_refused" | "connection_lost" | "model_not_found" | "context_overflow" | "server_error" | "unsupported";
+  message: string;
+  retryable: boolean;
+}
+
+export interface ModelSummary {
+  name: string;
+  size: number | null;
+  modified_at: string | null;
+}
+
+export interface PullProgress {
+  model: string;
+  status: string;
+  digest: string | null;
+  total: number | null;
+  completed: number | null;
+}
+
+export interface ConversationSettings {
+  conversation_id: number;
+  model_name?: string | null;
+  system_prompt?: string | null;
+  temperature?: number | null;
+  top_p?: number | null;
+  num_ctx?: number | null;
+  seed?: number | null;
+}
+
+export interface SearchHit {
+  message_id: number;
+  conversation_id: number;
+  conversation_title: string;
+  // 原文已做 HTML 转义，匹配部分用 <mark></mark> 包裹，可以直接用 v-html 渲染
+  snippet: string;
+  sender: string;
+  timestamp: number;
+}
+
+export interface AudioInputDevice {
+  name: string;
+  is_default: boolean;
+}
+
+export interface WordTiming {
+  word: string;
+  // 单位为秒
+  start: number;
+  end: number;
+  confidence: number;
+}
+
+export interface Transcription {
+  text: string;
+  words: WordTiming[];
+}
+
+export type ExportFormat = "markdown" | "json" | "html";
+
+export interface SkippedItem {
+  conversation: string;
+  message: string | null;
+  reason: string;
+}
+
+export interface ImportReport {
+  source: "chat_box" | "chatgpt" | "open_webui";
+  conversations: number;
+  messages: number;
+  skipped: SkippedItem[];
 }